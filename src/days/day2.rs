@@ -1,107 +1,883 @@
 // mod day2
 
 use std::cmp::max;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::error::Error;
+use std::fmt;
+use std::fs;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
 
-enum ColorCube {
-    Red(u32),
-    Green(u32),
-    Blue(u32),
+use super::next_arg;
+
+struct ColorCube {
+    color: String,
+    count: u32,
+    /// Column of the count in the game record, starting at 1.
+    column: usize,
+}
+
+/// Number of cubes of each colour contained in the bag.
+struct Bag {
+    limits: BTreeMap<String, u32>,
 }
 
 struct Game {
     id: u32,
+    draws: Vec<Vec<ColorCube>>,
 }
 
-impl TryFrom<&str> for ColorCube {
-    type Error = Box<dyn Error>;
-    fn try_from(value: &str) -> Result<Self, Box<dyn Error>> {
-        match value.trim().split_once(char::is_whitespace) {
-            Some((count, "red")) => Ok(ColorCube::Red(count.parse()?)),
-            Some((count, "green")) => Ok(ColorCube::Green(count.parse()?)),
-            Some((count, "blue")) => Ok(ColorCube::Blue(count.parse()?)),
-            _ => Err("Invalid Cube Type.".into()),
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Token<'a> {
+    Word(&'a str),
+    Number(&'a str),
+    Colon,
+    Semicolon,
+    Comma,
+    Other(char),
+}
+
+/// Errors of the game record grammar
+/// `Game <id>: <count> <colour>[, <count> <colour>]*[; ...]*`,
+/// each holding the column of the offending token, starting at 1.
+#[derive(Debug, PartialEq)]
+enum GameParseError {
+    BadHeader(usize),
+    BadCount(usize),
+    UnknownColour(usize),
+    DuplicateColour(usize),
+    MissingSeparator(usize),
+    EmptyDraw(usize),
+}
+
+impl fmt::Display for GameParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameParseError::BadHeader(column) => write!(f, "Bad game header at column {}.", column),
+            GameParseError::BadCount(column) => write!(f, "Bad cube count at column {}.", column),
+            GameParseError::UnknownColour(column) => {
+                write!(f, "Unknown colour at column {}.", column)
+            }
+            GameParseError::DuplicateColour(column) => {
+                write!(f, "Colour repeated within a draw at column {}.", column)
+            }
+            GameParseError::MissingSeparator(column) => {
+                write!(f, "Expected ',' or ';' at column {}.", column)
+            }
+            GameParseError::EmptyDraw(column) => write!(f, "Empty draw at column {}.", column),
         }
     }
 }
 
+impl Error for GameParseError {}
+
+/// Splits a game record into tokens paired with their column. Whitespace only
+/// separates tokens and is otherwise ignored.
+fn tokenize(line: &str) -> Vec<(usize, Token<'_>)> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().enumerate().peekable();
+    while let Some((char_idx, (byte_idx, chr))) = chars.next() {
+        let column = char_idx + 1;
+        let token = match chr {
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            ',' => Token::Comma,
+            c if c.is_whitespace() => continue,
+            c if c.is_ascii_digit() || c.is_alphabetic() => {
+                let is_part = |c: char| {
+                    if chr.is_ascii_digit() {
+                        c.is_ascii_digit()
+                    } else {
+                        c.is_alphanumeric() || c == '-' || c == '_'
+                    }
+                };
+                let mut end_idx = byte_idx + chr.len_utf8();
+                while let Some((_, (next_idx, next))) = chars.next_if(|(_, (_, c))| is_part(*c)) {
+                    end_idx = next_idx + next.len_utf8();
+                }
+                if chr.is_ascii_digit() {
+                    Token::Number(&line[byte_idx..end_idx])
+                } else {
+                    Token::Word(&line[byte_idx..end_idx])
+                }
+            }
+            c => Token::Other(c),
+        };
+        tokens.push((column, token));
+    }
+    tokens
+}
+
 impl TryFrom<&str> for Game {
+    type Error = GameParseError;
+    fn try_from(value: &str) -> Result<Self, GameParseError> {
+        let tokens = tokenize(value);
+        let end_column = value.chars().count() + 1;
+        let column_at = |idx: usize| tokens.get(idx).map_or(end_column, |(column, _)| *column);
+
+        let id = match tokens.get(..3) {
+            Some([(_, Token::Word("Game")), (column, Token::Number(id)), (_, Token::Colon)]) => {
+                id.parse().map_err(|_| GameParseError::BadHeader(*column))?
+            }
+            _ => {
+                let bad_idx = match tokens.as_slice() {
+                    [(_, Token::Word("Game")), (_, Token::Number(_)), ..] => 2,
+                    [(_, Token::Word("Game")), ..] => 1,
+                    _ => 0,
+                };
+                return Err(GameParseError::BadHeader(column_at(bad_idx)));
+            }
+        };
+
+        let mut draws = Vec::new();
+        let mut draw: Vec<ColorCube> = Vec::new();
+        let mut idx = 3;
+        loop {
+            let (column, count) = match tokens.get(idx) {
+                Some((column, Token::Number(count))) => (
+                    *column,
+                    count
+                        .parse()
+                        .map_err(|_| GameParseError::BadCount(*column))?,
+                ),
+                None | Some((_, Token::Semicolon)) if draw.is_empty() => {
+                    return Err(GameParseError::EmptyDraw(column_at(idx)))
+                }
+                _ => return Err(GameParseError::BadCount(column_at(idx))),
+            };
+            let color = match tokens.get(idx + 1) {
+                Some((_, Token::Word(color))) => color.to_string(),
+                _ => return Err(GameParseError::UnknownColour(column_at(idx + 1))),
+            };
+            if draw.iter().any(|cubes| cubes.color == color) {
+                return Err(GameParseError::DuplicateColour(column_at(idx + 1)));
+            }
+            draw.push(ColorCube {
+                color,
+                count,
+                column,
+            });
+
+            idx += 2;
+            match tokens.get(idx) {
+                Some((_, Token::Comma)) => {}
+                Some((_, Token::Semicolon)) => draws.push(std::mem::take(&mut draw)),
+                None => {
+                    draws.push(draw);
+                    return Ok(Game { id, draws });
+                }
+                Some((column, _)) => return Err(GameParseError::MissingSeparator(*column)),
+            }
+            idx += 1;
+        }
+    }
+}
+
+/// Re-emits the record in the normalised puzzle format.
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let draws: Vec<String> = self
+            .draws
+            .iter()
+            .map(|draw| {
+                draw.iter()
+                    .map(|cubes| format!("{} {}", cubes.count, cubes.color))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .collect();
+        write!(f, "Game {}: {}", self.id, draws.join("; "))
+    }
+}
+
+/// Parses a bag configuration such as `red=12,green=13,blue=14`. Entries may
+/// also be separated by newlines so the same format works for bag files.
+impl TryFrom<&str> for Bag {
     type Error = Box<dyn Error>;
     fn try_from(value: &str) -> Result<Self, Box<dyn Error>> {
-        match value.trim().split_once(char::is_whitespace) {
-            Some(("Game", id)) => Ok(Game { id: id.parse()? }),
-            _ => Err("Invalid Cube Type.".into()),
+        let mut limits = BTreeMap::new();
+        for entry in value
+            .split([',', '\n'])
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            match entry.split_once('=') {
+                Some((color, count)) if !color.trim().is_empty() => {
+                    if limits
+                        .insert(color.trim().to_string(), count.trim().parse()?)
+                        .is_some()
+                    {
+                        return Err(format!("Duplicate bag colour '{}'.", color.trim()).into());
+                    }
+                }
+                _ => return Err(format!("Invalid bag entry '{}'.", entry).into()),
+            }
+        }
+        if limits.is_empty() {
+            return Err("Bag configuration contains no colours.".into());
+        }
+        Ok(Bag { limits })
+    }
+}
+
+impl Default for Bag {
+    fn default() -> Self {
+        Bag {
+            limits: BTreeMap::from([
+                ("red".to_string(), 12),
+                ("green".to_string(), 13),
+                ("blue".to_string(), 14),
+            ]),
         }
     }
 }
 
-fn sum_valid_games<R>(reader: R) -> u32
+impl fmt::Display for Bag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entries: Vec<String> = self
+            .limits
+            .iter()
+            .map(|(color, count)| format!("{}={}", color, count))
+            .collect();
+        write!(f, "{}", entries.join(","))
+    }
+}
+
+impl Bag {
+    fn check_colours(&self, game: &Game) -> Result<(), Box<dyn Error>> {
+        match game
+            .draws
+            .iter()
+            .flatten()
+            .find(|cubes| !self.limits.contains_key(&cubes.color))
+        {
+            Some(cubes) => Err(format!(
+                "Unknown colour '{}' in game {} at column {}.",
+                cubes.color, game.id, cubes.column
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    fn admits(&self, game: &Game) -> Result<bool, Box<dyn Error>> {
+        self.check_colours(game)?;
+        Ok(game
+            .draws
+            .iter()
+            .flatten()
+            .all(|cubes| cubes.count <= self.limits[&cubes.color]))
+    }
+}
+
+impl Game {
+    /// Smallest number of cubes per colour needed to play this game.
+    fn minimum_set(&self) -> BTreeMap<&str, u32> {
+        self.draws
+            .iter()
+            .flatten()
+            .fold(BTreeMap::new(), |mut min_set, cubes| {
+                let entry = min_set.entry(cubes.color.as_str()).or_insert(0);
+                *entry = max(*entry, cubes.count);
+                min_set
+            })
+    }
+}
+
+/// Smallest bag in which every one of `games` is possible.
+fn minimum_bag<'a, I>(games: I) -> Bag
 where
-    R: BufRead,
+    I: IntoIterator<Item = &'a Game>,
 {
-    reader
-        .lines()
-        .filter_map(Result::ok)
-        .map(|line| {
-            if let Some((game_str, draws_str)) = line.split_once(':') {
-                let game = Game::try_from(game_str).unwrap();
-                for draw in draws_str.split("; ") {
-                    let (result_red, result_green, result_blue) = draw
-                        .split(", ")
-                        .map(ColorCube::try_from)
-                        .filter_map(Result::ok)
-                        .fold((0, 0, 0), |(red, green, blue), c| match c {
-                            ColorCube::Red(v) => (v, green, blue),
-                            ColorCube::Green(v) => (red, v, blue),
-                            ColorCube::Blue(v) => (red, green, v),
-                        });
-
-                    if result_red > 12 || result_green > 13 || result_blue > 14 {
-                        return 0;
-                    }
+    let mut limits = BTreeMap::new();
+    for game in games {
+        for (color, count) in game.minimum_set() {
+            let entry = limits.entry(color.to_string()).or_insert(0);
+            *entry = max(*entry, count);
+        }
+    }
+    Bag { limits }
+}
+
+fn select_games<'a>(games: &'a [Game], ids: &[u32]) -> Result<Vec<&'a Game>, Box<dyn Error>> {
+    ids.iter()
+        .map(|id| {
+            games
+                .iter()
+                .find(|game| game.id == *id)
+                .ok_or_else(|| format!("No game with id {}.", id).into())
+        })
+        .collect()
+}
+
+/// Calls `visit` with every combination of per-colour counts taken from
+/// `candidates` whose total does not exceed `remaining`.
+fn visit_bags<F>(candidates: &[Vec<u32>], remaining: u32, counts: &mut Vec<u32>, visit: &mut F)
+where
+    F: FnMut(&[u32]),
+{
+    let Some((values, rest)) = candidates.split_first() else {
+        visit(counts);
+        return;
+    };
+    for value in values.iter().take_while(|value| **value <= remaining) {
+        counts.push(*value);
+        visit_bags(rest, remaining - value, counts, visit);
+        counts.pop();
+    }
+}
+
+/// Finds the bags holding `total` cubes that maximise the sum of ids of the
+/// possible games. Adding cubes to a bag never makes a game impossible, so
+/// only the tight bags are returned, i.e. the minimum bags of each best set
+/// of games. Any bag containing one of them and `total` cubes is optimal.
+fn best_bags(games: &[Game], total: u32) -> (u32, Vec<Bag>) {
+    let colors: Vec<String> = minimum_bag(games).limits.into_keys().collect();
+    let min_sets: Vec<Vec<u32>> = games
+        .iter()
+        .map(|game| {
+            let min_set = game.minimum_set();
+            colors
+                .iter()
+                .map(|color| min_set.get(color.as_str()).copied().unwrap_or(0))
+                .collect()
+        })
+        .collect();
+    // A bag count between two required counts admits the same games as the
+    // lower one, so only counts required by some game need to be tried.
+    let candidates: Vec<Vec<u32>> = (0..colors.len())
+        .map(|color_idx| {
+            let values: BTreeSet<u32> = min_sets
+                .iter()
+                .map(|min_set| min_set[color_idx])
+                .chain([0])
+                .collect();
+            values.into_iter().collect()
+        })
+        .collect();
+
+    let mut best_sum = 0;
+    let mut best: BTreeSet<Vec<u32>> = BTreeSet::new();
+    visit_bags(&candidates, total, &mut Vec::new(), &mut |counts| {
+        let mut tight = vec![0; counts.len()];
+        let mut id_sum = 0;
+        for (game, min_set) in games.iter().zip(&min_sets) {
+            if min_set.iter().zip(counts).all(|(need, have)| need <= have) {
+                id_sum += game.id;
+                for (tight_count, need) in tight.iter_mut().zip(min_set) {
+                    *tight_count = max(*tight_count, *need);
                 }
-                return game.id;
             }
-            return 0;
+        }
+        if id_sum > best_sum {
+            best_sum = id_sum;
+            best.clear();
+        }
+        if id_sum == best_sum {
+            best.insert(tight);
+        }
+    });
+
+    let bags = best
+        .into_iter()
+        .map(|counts| Bag {
+            limits: colors.iter().cloned().zip(counts).collect(),
+        })
+        .collect();
+    (best_sum, bags)
+}
+
+/// Natural logarithms of `0!..=n!`.
+fn ln_factorials(n: u32) -> Vec<f64> {
+    (1..=n).fold(vec![0.0], |mut ln_fact, k| {
+        ln_fact.push(ln_fact[ln_fact.len() - 1] + f64::from(k).ln());
+        ln_fact
+    })
+}
+
+/// Log-likelihood of the draws of `game` when each draw takes its cubes from
+/// the bag without replacement and puts them back before the next draw. The
+/// bag holds `counts[i]` cubes of `colors[i]`.
+fn game_log_likelihood(game: &Game, colors: &[String], counts: &[u32], ln_fact: &[f64]) -> f64 {
+    let ln_choose = |n: u32, k: u32| {
+        if k > n {
+            f64::NEG_INFINITY
+        } else {
+            ln_fact[n as usize] - ln_fact[k as usize] - ln_fact[(n - k) as usize]
+        }
+    };
+    let total = counts.iter().sum();
+
+    game.draws
+        .iter()
+        .map(|draw| {
+            let mut drawn_total = 0;
+            let mut log_likelihood = 0.0;
+            for (color, count) in colors.iter().zip(counts) {
+                let drawn = draw
+                    .iter()
+                    .filter(|cubes| cubes.color == *color)
+                    .map(|cubes| cubes.count)
+                    .sum();
+                drawn_total += drawn;
+                log_likelihood += ln_choose(*count, drawn);
+            }
+            log_likelihood - ln_choose(total, drawn_total)
         })
         .sum()
 }
 
-fn sum_of_minimum_set_powers<R>(reader: R) -> u32
+/// Calls `visit` with every way of distributing `remaining` cubes over the
+/// colours on top of the counts already in `counts`.
+fn visit_compositions<F>(counts: &mut [u32], idx: usize, remaining: u32, visit: &mut F)
 where
-    R: BufRead,
+    F: FnMut(&[u32]),
 {
-    reader
-        .lines()
-        .filter_map(Result::ok)
-        .map(|line| {
-            if let Some((_, draws_str)) = line.split_once(':') {
-                let (red_max, green_max, blue_max) = draws_str
-                    .split("; ")
-                    .flat_map(|draw| draw.split(", "))
-                    .map(ColorCube::try_from)
-                    .filter_map(Result::ok)
-                    .fold((0, 0, 0), |(red, green, blue), cubes| match cubes {
-                        ColorCube::Red(v) => (max(red, v), green, blue),
-                        ColorCube::Green(v) => (red, max(green, v), blue),
-                        ColorCube::Blue(v) => (red, green, max(blue, v)),
-                    });
-
-                return red_max * green_max * blue_max;
-            }
-            0
+    if counts.is_empty() {
+        return;
+    }
+    if idx + 1 == counts.len() {
+        counts[idx] += remaining;
+        visit(counts);
+        counts[idx] -= remaining;
+        return;
+    }
+    for extra in 0..=remaining {
+        counts[idx] += extra;
+        visit_compositions(counts, idx + 1, remaining - extra, visit);
+        counts[idx] -= extra;
+    }
+}
+
+/// Cube counts of a candidate bag and the log-likelihood of all games for it.
+type CandidateBag = (Vec<u32>, f64);
+
+/// Log-likelihoods of all games for every candidate bag holding `total` cubes
+/// of `colors`, sorted from most to least likely. Bags lacking cubes for one
+/// of the observed draws have zero likelihood and are left out.
+fn estimate_bags(
+    games: &[Game],
+    colors: &[String],
+    total: u32,
+) -> Result<Vec<CandidateBag>, Box<dyn Error>> {
+    let observed = minimum_bag(games);
+    let mut counts: Vec<u32> = colors
+        .iter()
+        .map(|color| observed.limits.get(color).copied().unwrap_or(0))
+        .collect();
+    let required: u32 = counts.iter().sum();
+    if required > total {
+        return Err(format!(
+            "The games need at least {} cubes, more than the {} in the bag.",
+            required, total
+        )
+        .into());
+    }
+
+    let ln_fact = ln_factorials(total);
+    let mut candidates = Vec::new();
+    visit_compositions(&mut counts, 0, total - required, &mut |counts| {
+        let log_likelihood = games
+            .iter()
+            .map(|game| game_log_likelihood(game, colors, counts, &ln_fact))
+            .sum::<f64>();
+        if log_likelihood.is_finite() {
+            candidates.push((counts.to_vec(), log_likelihood));
+        }
+    });
+    candidates.sort_by(|lhs, rhs| rhs.1.total_cmp(&lhs.1));
+    Ok(candidates)
+}
+
+/// Prints the maximum-likelihood bag of `total` cubes and the posterior over
+/// all candidate bags under a uniform prior.
+fn print_bag_estimate(games: &[Game], bag: &Bag, total: u32) -> Result<(), Box<dyn Error>> {
+    let colors: Vec<String> = bag
+        .limits
+        .keys()
+        .chain(minimum_bag(games).limits.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let candidates = estimate_bags(games, &colors, total)?;
+    let to_bag = |counts: &[u32]| Bag {
+        limits: colors.iter().cloned().zip(counts.iter().copied()).collect(),
+    };
+
+    let (mle_counts, mle_log_likelihood) = candidates
+        .first()
+        .ok_or_else(|| format!("No bag with {} cubes can produce the draws.", total))?;
+    println!(
+        "Maximum likelihood bag with {} cubes: {} (log-likelihood {:.3})",
+        total,
+        to_bag(mle_counts),
+        mle_log_likelihood
+    );
+
+    let evidence = mle_log_likelihood
+        + candidates
+            .iter()
+            .map(|(_, log_likelihood)| (log_likelihood - mle_log_likelihood).exp())
+            .sum::<f64>()
+            .ln();
+    let posterior: Vec<f64> = candidates
+        .iter()
+        .map(|(_, log_likelihood)| (log_likelihood - evidence).exp())
+        .collect();
+    let means: Vec<String> = colors
+        .iter()
+        .enumerate()
+        .map(|(color_idx, color)| {
+            let mean: f64 = candidates
+                .iter()
+                .zip(&posterior)
+                .map(|((counts, _), p)| p * f64::from(counts[color_idx]))
+                .sum();
+            format!("{}={:.2}", color, mean)
         })
-        .sum()
+        .collect();
+    println!("Posterior mean bag: {}", means.join(","));
+    println!("Most probable bags out of {}:", candidates.len());
+    for ((counts, _), p) in candidates.iter().zip(&posterior).take(5) {
+        println!("  {} p={:.4}", to_bag(counts), p);
+    }
+
+    let ln_fact = ln_factorials(total);
+    println!("Per game log-likelihood under the maximum likelihood bag:");
+    for game in games {
+        println!(
+            "  Game {}: {:.3}",
+            game.id,
+            game_log_likelihood(game, &colors, mle_counts, &ln_fact)
+        );
+    }
+    Ok(())
 }
 
-pub fn print_answer() {
-    let reader = BufReader::new(File::open("data/input_day2").unwrap());
-    println!("Sum of valid game ids: {}", sum_valid_games(reader));
+fn parse_games<R>(reader: R) -> Result<Vec<Game>, Box<dyn Error>>
+where
+    R: BufRead,
+{
+    let mut games = Vec::new();
+    for (line_idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            games.push(
+                Game::try_from(line.as_str())
+                    .map_err(|err| format!("Line {}: {}", line_idx + 1, err))?,
+            );
+        }
+    }
+    Ok(games)
+}
+
+fn sum_valid_games(games: &[Game], bag: &Bag) -> Result<u64, Box<dyn Error>> {
+    let mut sum: u64 = 0;
+    for game in games {
+        if bag.admits(game)? {
+            sum = sum
+                .checked_add(u64::from(game.id))
+                .ok_or("Sum of valid game ids overflows u64.")?;
+        }
+    }
+    Ok(sum)
+}
+
+/// The power of a set is the product of its cube counts over all colours of
+/// the bag, so a colour never drawn in a game contributes a factor of zero.
+fn sum_of_minimum_set_powers(games: &[Game], bag: &Bag) -> Result<u64, Box<dyn Error>> {
+    let mut sum: u64 = 0;
+    for game in games {
+        bag.check_colours(game)?;
+        let min_set = game.minimum_set();
+        let power = bag
+            .limits
+            .keys()
+            .map(|color| min_set.get(color.as_str()).copied().unwrap_or(0))
+            .try_fold(1u64, |power, count| power.checked_mul(u64::from(count)))
+            .ok_or_else(|| format!("Minimum set power of game {} overflows u64.", game.id))?;
+        sum = sum
+            .checked_add(power)
+            .ok_or("Sum of minimum set powers overflows u64.")?;
+    }
+    Ok(sum)
+}
+
+/// Runs day 2 with command line options:
+///
+/// `--input <path>` reads the game records from `path`,
+/// `--bag <spec>` sets the bag contents, e.g. `red=12,green=13,blue=14,yellow=3`,
+/// `--bag-file <path>` reads the bag contents in the same format from a file,
+/// `--min-bag` prints the smallest bag in which all games are possible,
+/// `--min-bag-ids <ids>` does the same for a comma separated list of game ids,
+/// `--best-bags <total>` prints the bags of `total` cubes maximising the sum
+/// of possible game ids,
+/// `--estimate <total>` estimates the contents of a bag of `total` cubes from
+/// the recorded draws, using the colours of the games and of the bag.
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut input_path = "data/input_day2";
+    let mut bag = Bag::default();
+    let mut min_bag = false;
+    let mut min_bag_ids: Option<Vec<u32>> = None;
+    let mut best_bags_total: Option<u32> = None;
+    let mut estimate_total: Option<u32> = None;
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--input" => input_path = next_arg(&mut args_iter, arg)?,
+            "--bag" => bag = Bag::try_from(next_arg(&mut args_iter, arg)?)?,
+            "--bag-file" => {
+                bag = Bag::try_from(fs::read_to_string(next_arg(&mut args_iter, arg)?)?.as_str())?
+            }
+            "--min-bag" => min_bag = true,
+            "--min-bag-ids" => {
+                min_bag_ids = Some(
+                    next_arg(&mut args_iter, arg)?
+                        .split(',')
+                        .map(|id| id.trim().parse())
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            "--best-bags" => best_bags_total = Some(next_arg(&mut args_iter, arg)?.parse()?),
+            "--estimate" => estimate_total = Some(next_arg(&mut args_iter, arg)?.parse()?),
+            _ => return Err(format!("Unknown day 2 option '{}'.", arg).into()),
+        }
+    }
+
+    let games = parse_games(BufReader::new(File::open(input_path)?))?;
+    println!("Sum of valid game ids: {}", sum_valid_games(&games, &bag)?);
     println!(
         "Part 2 Sum of min set powers: {}",
-        sum_of_minimum_set_powers(BufReader::new(File::open("data/input_day2").unwrap()))
+        sum_of_minimum_set_powers(&games, &bag)?
     );
+
+    if min_bag {
+        println!("Minimum bag for all games: {}", minimum_bag(&games));
+    }
+    if let Some(ids) = min_bag_ids {
+        println!(
+            "Minimum bag for games {:?}: {}",
+            ids,
+            minimum_bag(select_games(&games, &ids)?)
+        );
+    }
+    if let Some(total) = best_bags_total {
+        let (id_sum, bags) = best_bags(&games, total);
+        println!("Best sum of game ids with {} cubes: {}", total, id_sum);
+        for bag in bags {
+            let spare = total - bag.limits.values().sum::<u32>();
+            println!("  {} (+{} spare cubes)", bag, spare);
+        }
+    }
+    if let Some(total) = estimate_total {
+        print_bag_estimate(&games, &bag, total)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_game() {
+    let game = Game::try_from("  Game 3 :8 green,6 blue ;  20  red\t,5 blue").unwrap();
+    assert_eq!(game.to_string(), "Game 3: 8 green, 6 blue; 20 red, 5 blue");
+    assert_eq!(
+        Game::try_from(game.to_string().as_str())
+            .unwrap()
+            .to_string(),
+        game.to_string()
+    );
+
+    let parse_err = |line: &str| Game::try_from(line).err();
+    assert_eq!(
+        parse_err("Gaem 1: 3 red"),
+        Some(GameParseError::BadHeader(1))
+    );
+    assert_eq!(
+        parse_err("Game x: 3 red"),
+        Some(GameParseError::BadHeader(6))
+    );
+    assert_eq!(
+        parse_err("Game 1 3 red"),
+        Some(GameParseError::BadHeader(8))
+    );
+    assert_eq!(parse_err("Game 1: red"), Some(GameParseError::BadCount(9)));
+    assert_eq!(
+        parse_err("Game 1: 3 red,"),
+        Some(GameParseError::BadCount(15))
+    );
+    assert_eq!(
+        parse_err("Game 1: 99999999999 red"),
+        Some(GameParseError::BadCount(9))
+    );
+    assert_eq!(
+        parse_err("Game 1: 3, 4 red"),
+        Some(GameParseError::UnknownColour(10))
+    );
+    assert_eq!(
+        parse_err("Game 1: 3 dark red"),
+        Some(GameParseError::MissingSeparator(16))
+    );
+    assert_eq!(
+        parse_err("Game 1: 3 red 4 blue"),
+        Some(GameParseError::MissingSeparator(15))
+    );
+    assert_eq!(
+        parse_err("Game 1: 3 red: 4 blue"),
+        Some(GameParseError::MissingSeparator(14))
+    );
+    assert_eq!(
+        parse_err("Game 1: 3 red;; 1 red"),
+        Some(GameParseError::EmptyDraw(15))
+    );
+    assert_eq!(parse_err("Game 1:"), Some(GameParseError::EmptyDraw(8)));
+    assert_eq!(
+        parse_err("Game 1: 1 red, 1 red"),
+        Some(GameParseError::DuplicateColour(18))
+    );
+    assert!(Game::try_from("Game 1: 1 red; 1 red").is_ok());
+}
+
+#[cfg(test)]
+const EXAMPLE_GAMES: &str = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+Game 2: 1 blue, 2 green; 3 green, 4 blue, 1 red; 1 green, 1 blue
+Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
+Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red
+Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
+
+#[test]
+fn test_bag_config() {
+    let games = parse_games(EXAMPLE_GAMES.as_bytes()).unwrap();
+    let bag = Bag::try_from(" red = 12, green=13 ,blue=14").unwrap();
+    assert_eq!(bag.limits, Bag::default().limits);
+    assert_eq!(sum_valid_games(&games, &bag).unwrap(), 8);
+    assert_eq!(sum_of_minimum_set_powers(&games, &bag).unwrap(), 2286);
+    let bag_file = Bag::try_from("red=12\ngreen=13\n\nblue=14\n").unwrap();
+    assert_eq!(bag_file.limits, bag.limits);
+
+    let bag_err = |spec: &str| Bag::try_from(spec).err().map(|err| err.to_string());
+    assert_eq!(
+        bag_err("red=1,green=2,red=3"),
+        Some("Duplicate bag colour 'red'.".to_string())
+    );
+    assert_eq!(
+        bag_err(""),
+        Some("Bag configuration contains no colours.".to_string())
+    );
+    assert_eq!(
+        bag_err(" ,\n"),
+        Some("Bag configuration contains no colours.".to_string())
+    );
+    assert_eq!(
+        bag_err("red=1,green"),
+        Some("Invalid bag entry 'green'.".to_string())
+    );
+    assert_eq!(bag_err("=1"), Some("Invalid bag entry '=1'.".to_string()));
+    assert!(bag_err("red=x").is_some());
+
+    // Game 1 draws blue cubes, which this bag knows nothing about.
+    let no_blue = Bag::try_from("red=12,green=13").unwrap();
+    assert_eq!(
+        sum_valid_games(&games, &no_blue).unwrap_err().to_string(),
+        "Unknown colour 'blue' in game 1 at column 9."
+    );
+    assert!(sum_of_minimum_set_powers(&games, &no_blue).is_err());
+
+    // Powers and sums are computed in u64 and fail instead of overflowing.
+    let big = parse_games("Game 1: 100000 red, 100000 green, 1 blue".as_bytes()).unwrap();
+    let big_bag = Bag::try_from("red=100000,green=100000,blue=1").unwrap();
+    assert_eq!(
+        sum_of_minimum_set_powers(&big, &big_bag).unwrap(),
+        10_000_000_000
+    );
+    let max = u32::MAX;
+    let huge = parse_games(
+        format!(
+            "Game 1: {} red, {} green, {} blue\nGame 2: 1 red",
+            max, max, max
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let huge_bag =
+        Bag::try_from(format!("red={},green={},blue={}", max, max, max).as_str()).unwrap();
+    assert_eq!(sum_valid_games(&huge, &huge_bag).unwrap(), 3);
+    assert_eq!(
+        sum_of_minimum_set_powers(&huge, &huge_bag)
+            .unwrap_err()
+            .to_string(),
+        "Minimum set power of game 1 overflows u64."
+    );
+}
+
+#[test]
+fn test_minimum_and_best_bags() {
+    let games = parse_games(EXAMPLE_GAMES.as_bytes()).unwrap();
+    assert_eq!(minimum_bag(&games).to_string(), "blue=15,green=13,red=20");
+    let selected = select_games(&games, &[1, 2]).unwrap();
+    assert_eq!(minimum_bag(selected).to_string(), "blue=6,green=3,red=4");
+    assert_eq!(
+        select_games(&games, &[1, 7])
+            .err()
+            .map(|err| err.to_string()),
+        Some("No game with id 7.".to_string())
+    );
+
+    let best = |total| {
+        let (id_sum, bags) = best_bags(&games, total);
+        let bags: Vec<String> = bags.iter().map(|bag| bag.to_string()).collect();
+        (id_sum, bags)
+    };
+    // The games need 12, 8, 39, 32 and 11 cubes on their own.
+    assert_eq!(best(7), (0, vec!["blue=0,green=0,red=0".to_string()]));
+    assert_eq!(best(8), (2, vec!["blue=4,green=3,red=1".to_string()]));
+    assert_eq!(best(12), (5, vec!["blue=2,green=3,red=6".to_string()]));
+    // Games 2 and 5 together need blue=4, green=3 and red=6.
+    assert_eq!(best(13), (7, vec!["blue=4,green=3,red=6".to_string()]));
+    assert_eq!(best(34).0, 1 + 2 + 4 + 5);
+    assert_eq!(best(48), (15, vec!["blue=15,green=13,red=20".to_string()]));
+}
+
+#[test]
+fn test_estimate_bags() {
+    let games = parse_games("Game 1: 1 red; 1 red, 1 blue".as_bytes()).unwrap();
+    let colors = vec!["blue".to_string(), "red".to_string()];
+    let ln_fact = ln_factorials(3);
+    // Drawing 1 red cube out of 1 blue and 2 red ones has the probability
+    // C(2,1)/C(3,1) = 2/3, drawing 1 of each C(1,1)*C(2,1)/C(3,2) = 2/3.
+    let log_likelihood = game_log_likelihood(&games[0], &colors, &[1, 2], &ln_fact);
+    assert!((log_likelihood - 2.0 * (2.0f64 / 3.0).ln()).abs() < 1e-12);
+    // With 2 blue cubes and 1 red one: 1/3 and 2/3.
+    let log_likelihood = game_log_likelihood(&games[0], &colors, &[2, 1], &ln_fact);
+    assert!((log_likelihood - (2.0f64 / 9.0).ln()).abs() < 1e-12);
+    assert_eq!(
+        game_log_likelihood(&games[0], &colors, &[0, 3], &ln_fact),
+        f64::NEG_INFINITY
+    );
+
+    let candidates = estimate_bags(&games, &colors, 3).unwrap();
+    let counts: Vec<&[u32]> = candidates
+        .iter()
+        .map(|(counts, _)| counts.as_slice())
+        .collect();
+    assert_eq!(counts, vec![&[1, 2][..], &[2, 1][..]]);
+    assert!(candidates[0].1 > candidates[1].1);
+
+    let candidates = estimate_bags(&games, &colors, 5).unwrap();
+    assert_eq!(candidates.len(), 4);
+    // 4/5 * 4/10 = 0.32 for blue=1,red=4 but 3/5 * 6/10 = 0.36 for blue=2,red=3.
+    assert_eq!(candidates[0].0, vec![2, 3]);
+    assert!(candidates.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+    assert_eq!(
+        estimate_bags(&games, &colors, 1)
+            .err()
+            .map(|err| err.to_string()),
+        Some("The games need at least 2 cubes, more than the 1 in the bag.".to_string())
+    );
+}
+
+pub fn print_answer() {
+    run(&[]).unwrap();
 }
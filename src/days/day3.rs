@@ -1,154 +1,821 @@
 // mod day2
 
-use std::cmp::min;
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
-use std::usize;
-
-fn check_for_adjacent_part(input: &Vec<String>, row_idx: usize, match_idx: usize) -> bool {
-    let num_cols = input[row_idx].len();
-    let check = |c: char| c != '.' && !c.is_numeric();
-    let check_upper = &input[row_idx.saturating_sub(1)]
-        [match_idx.saturating_sub(1)..min(match_idx + 2, num_cols)];
-    let check_middle = &input[row_idx][match_idx.saturating_sub(1)..min(match_idx + 2, num_cols)];
-    let check_lower = &input[min(row_idx + 1, input.len() - 1)]
-        [match_idx.saturating_sub(1)..min(match_idx + 2, num_cols)];
-
-    //println!("{} {} {}", check_upper, check_middle, check_lower);
-    check_upper.contains(check) || check_middle.contains(check) || check_lower.contains(check)
-}
-
-fn sum_of_part_nums(input: &Vec<String>) -> u32 {
-    let mut part_nums: Vec<u32> = Vec::new();
-    for row_idx in 0..input.len() {
-        let line = &input[row_idx];
-        let (part_num, _, adjacent) = line.match_indices(char::is_numeric).fold(
-            (0u32, 0usize, false),
-            |(part_num, position, is_adjacent), (match_idx, digit)| {
-                if (match_idx - position) <= 1 {
-                    return (
-                        10 * part_num + digit.parse::<u32>().unwrap(),
-                        match_idx,
-                        is_adjacent || check_for_adjacent_part(input, row_idx, match_idx),
-                    );
-                } else if is_adjacent {
-                    part_nums.push(part_num);
+use std::ops::Range;
+
+use super::next_arg;
+
+/// A number of the schematic covering the columns `cols` of `row`.
+#[derive(Debug)]
+struct PartNumber {
+    row: usize,
+    cols: Range<usize>,
+    value: u32,
+}
+
+#[derive(Debug)]
+struct Symbol {
+    row: usize,
+    col: usize,
+    chr: char,
+}
+
+/// Content of a single schematic position, referring into `Schematic::numbers`
+/// and `Schematic::symbols`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cell {
+    Empty,
+    Number(usize),
+    Symbol(usize),
+}
+
+/// Number of adjacent part numbers a symbol needs to count as gear.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+/// Operation combining the adjacent part numbers of a gear into its ratio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combine {
+    Product,
+    Sum,
+    Max,
+}
+
+struct GearRule {
+    symbols: Vec<char>,
+    arity: Arity,
+    combine: Combine,
+}
+
+struct Gear<'a> {
+    symbol_idx: usize,
+    symbol: &'a Symbol,
+    part_nums: Vec<u32>,
+    ratio: u64,
+}
+
+/// How a schematic character is shown when rendering. Gears refer to their
+/// index in the list of found gears.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Highlight {
+    Plain,
+    Counted,
+    Uncounted,
+    Symbol,
+    Gear(usize),
+}
+
+/// A connected group of symbols and the part numbers linking them.
+struct Assembly {
+    symbols: Vec<usize>,
+    numbers: Vec<usize>,
+}
+
+struct Schematic {
+    numbers: Vec<PartNumber>,
+    symbols: Vec<Symbol>,
+    cells: Vec<Vec<Cell>>,
+    /// Symbols adjacent to each number, indexed like `numbers`.
+    number_symbols: Vec<Vec<usize>>,
+    /// Numbers adjacent to each symbol, indexed like `symbols`.
+    symbol_numbers: Vec<Vec<usize>>,
+}
+
+impl TryFrom<&[String]> for Schematic {
+    type Error = Box<dyn Error>;
+    fn try_from(input: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut numbers: Vec<PartNumber> = Vec::new();
+        let mut symbols = Vec::new();
+        let mut cells = Vec::new();
+        for (row, line) in input.iter().enumerate() {
+            let mut row_cells = Vec::new();
+            for (col, chr) in line.chars().enumerate() {
+                let cell = match chr {
+                    '.' => Cell::Empty,
+                    c if c.is_ascii_digit() => {
+                        let digit = c.to_digit(10).unwrap();
+                        match numbers.last_mut() {
+                            Some(number) if number.row == row && number.cols.end == col => {
+                                number.value = number
+                                    .value
+                                    .checked_mul(10)
+                                    .and_then(|value| value.checked_add(digit))
+                                    .ok_or_else(|| {
+                                        format!(
+                                            "Number at line {} column {} overflows u32.",
+                                            row + 1,
+                                            number.cols.start + 1
+                                        )
+                                    })?;
+                                number.cols.end += 1;
+                            }
+                            _ => numbers.push(PartNumber {
+                                row,
+                                cols: col..(col + 1),
+                                value: digit,
+                            }),
+                        }
+                        Cell::Number(numbers.len() - 1)
+                    }
+                    c if c.is_whitespace() => Cell::Empty,
+                    c => {
+                        symbols.push(Symbol { row, col, chr: c });
+                        Cell::Symbol(symbols.len() - 1)
+                    }
+                };
+                row_cells.push(cell);
+            }
+            cells.push(row_cells);
+        }
+
+        let mut schematic = Schematic {
+            number_symbols: vec![Vec::new(); numbers.len()],
+            symbol_numbers: vec![Vec::new(); symbols.len()],
+            numbers,
+            symbols,
+            cells,
+        };
+        for symbol_idx in 0..schematic.symbols.len() {
+            let Symbol { row, col, .. } = schematic.symbols[symbol_idx];
+            for cell in schematic.neighbours(row, col) {
+                if let Cell::Number(number_idx) = cell {
+                    if !schematic.symbol_numbers[symbol_idx].contains(&number_idx) {
+                        schematic.symbol_numbers[symbol_idx].push(number_idx);
+                        schematic.number_symbols[number_idx].push(symbol_idx);
+                    }
                 }
-                (
-                    digit.parse::<u32>().unwrap(),
-                    match_idx,
-                    check_for_adjacent_part(input, row_idx, match_idx),
-                )
-            },
-        );
+            }
+        }
+        Ok(schematic)
+    }
+}
+
+impl Schematic {
+    /// Positions outside the parsed schematic, including those past the end of
+    /// a shorter row or on an empty line, are treated as empty.
+    fn cell(&self, row: usize, col: usize) -> Cell {
+        self.cells
+            .get(row)
+            .and_then(|row_cells| row_cells.get(col))
+            .copied()
+            .unwrap_or(Cell::Empty)
+    }
+
+    /// The up to eight cells surrounding `row` and `col`.
+    fn neighbours(&self, row: usize, col: usize) -> Vec<Cell> {
+        (row.saturating_sub(1)..=(row + 1))
+            .flat_map(|n_row| (col.saturating_sub(1)..=(col + 1)).map(move |n_col| (n_row, n_col)))
+            .filter(|pos| *pos != (row, col))
+            .map(|(n_row, n_col)| self.cell(n_row, n_col))
+            .collect()
+    }
+
+    fn numbers_adjacent_to_symbol(&self, symbol_idx: usize) -> &[usize] {
+        &self.symbol_numbers[symbol_idx]
+    }
+
+    fn symbols_adjacent_to_number(&self, number_idx: usize) -> &[usize] {
+        &self.number_symbols[number_idx]
+    }
+}
 
-        if adjacent {
-            part_nums.push(part_num);
+/// Parses `k` as exactly `k` and `k+` as at least `k` part numbers.
+impl TryFrom<&str> for Arity {
+    type Error = Box<dyn Error>;
+    fn try_from(value: &str) -> Result<Self, Box<dyn Error>> {
+        match value.trim().strip_suffix('+') {
+            Some(count) => Ok(Arity::AtLeast(count.parse()?)),
+            None => Ok(Arity::Exactly(value.trim().parse()?)),
         }
     }
+}
 
-    part_nums.iter().sum()
+impl TryFrom<&str> for Combine {
+    type Error = Box<dyn Error>;
+    fn try_from(value: &str) -> Result<Self, Box<dyn Error>> {
+        match value.trim() {
+            "product" => Ok(Combine::Product),
+            "sum" => Ok(Combine::Sum),
+            "max" => Ok(Combine::Max),
+            _ => Err(format!("Unknown gear ratio operation '{}'.", value.trim()).into()),
+        }
+    }
 }
 
-fn get_enclosing_range_from_subrange(
-    row: &str,
-    begin_idx: usize,
-    end_idx: usize,
-) -> (usize, usize) {
-    let mut new_begin = begin_idx;
-    // move left border
-    if row.chars().nth(begin_idx).unwrap().is_numeric() {
-        for _ in row[0..begin_idx]
-            .chars()
-            .rev()
-            .take_while(|c| c.is_numeric())
-        {
-            new_begin -= 1;
+/// The puzzle's rule: a `*` next to exactly two part numbers, whose ratio is
+/// their product.
+impl Default for GearRule {
+    fn default() -> Self {
+        GearRule {
+            symbols: vec!['*'],
+            arity: Arity::Exactly(2),
+            combine: Combine::Product,
         }
     }
+}
 
-    let mut new_end = end_idx;
+impl GearRule {
+    fn accepts(&self, symbol: &Symbol, num_parts: usize) -> bool {
+        self.symbols.contains(&symbol.chr)
+            && match self.arity {
+                Arity::Exactly(count) => num_parts == count,
+                Arity::AtLeast(count) => num_parts >= count,
+            }
+    }
 
-    // move right border
-    if row.chars().nth(end_idx).unwrap().is_numeric() {
-        for _ in row[(end_idx + 1)..row.len()]
-            .chars()
-            .take_while(|c| c.is_numeric())
-        {
-            new_end += 1;
+    /// The ratio of a gear with the given part numbers, `None` if it
+    /// overflows `u64`.
+    fn ratio(&self, part_nums: &[u32]) -> Option<u64> {
+        let mut values = part_nums.iter().map(|value| u64::from(*value));
+        match self.combine {
+            Combine::Product => values.try_fold(1u64, |ratio, value| ratio.checked_mul(value)),
+            Combine::Sum => values.try_fold(0u64, |ratio, value| ratio.checked_add(value)),
+            Combine::Max => Some(values.max().unwrap_or(0)),
         }
     }
+}
 
-    (new_begin, new_end)
+/// Sums the numbers next to a symbol. The `u64` sum of `u32` values can not
+/// overflow for any schematic that fits in memory.
+fn sum_of_part_nums(schematic: &Schematic) -> u64 {
+    schematic
+        .numbers
+        .iter()
+        .enumerate()
+        .filter(|(number_idx, _)| !schematic.symbols_adjacent_to_number(*number_idx).is_empty())
+        .map(|(_, number)| u64::from(number.value))
+        .sum()
 }
 
-fn sum_of_gear_ratios(input: &Vec<String>) -> u32 {
-    let mut gear_ratios: Vec<u32> = Vec::new();
-    for row_idx in 0..input.len() {
-        gear_ratios.extend(
-            input[row_idx]
-                .match_indices(|c: char| c == '*')
-                .map(|(match_idx, _)| get_gear_ratio(input, row_idx, match_idx)),
-        );
+fn find_gears<'a>(
+    schematic: &'a Schematic,
+    rule: &GearRule,
+) -> Result<Vec<Gear<'a>>, Box<dyn Error>> {
+    schematic
+        .symbols
+        .iter()
+        .enumerate()
+        .map(|(symbol_idx, symbol)| {
+            let part_nums: Vec<u32> = schematic
+                .numbers_adjacent_to_symbol(symbol_idx)
+                .iter()
+                .map(|number_idx| schematic.numbers[*number_idx].value)
+                .collect();
+            (symbol_idx, symbol, part_nums)
+        })
+        .filter(|(_, symbol, part_nums)| rule.accepts(symbol, part_nums.len()))
+        .map(|(symbol_idx, symbol, part_nums)| {
+            let ratio = rule.ratio(&part_nums).ok_or_else(|| {
+                format!(
+                    "Ratio of the gear at line {} column {} overflows u64.",
+                    symbol.row + 1,
+                    symbol.col + 1
+                )
+            })?;
+            Ok(Gear {
+                symbol_idx,
+                symbol,
+                ratio,
+                part_nums,
+            })
+        })
+        .collect()
+}
+
+fn sum_of_gear_ratios(gears: &[Gear]) -> Result<u64, Box<dyn Error>> {
+    gears
+        .iter()
+        .try_fold(0u64, |sum, gear| sum.checked_add(gear.ratio))
+        .ok_or_else(|| "Sum of gear ratios overflows u64.".into())
+}
+
+/// Connected components of the graph linking every symbol to its adjacent
+/// numbers. Numbers without any adjacent symbol are not part of an assembly.
+fn find_assemblies(schematic: &Schematic) -> Vec<Assembly> {
+    let mut visited_symbols = vec![false; schematic.symbols.len()];
+    let mut visited_numbers = vec![false; schematic.numbers.len()];
+    let mut assemblies = Vec::new();
+    for start_idx in 0..schematic.symbols.len() {
+        if visited_symbols[start_idx] {
+            continue;
+        }
+        visited_symbols[start_idx] = true;
+        let mut assembly = Assembly {
+            symbols: Vec::new(),
+            numbers: Vec::new(),
+        };
+        let mut stack = vec![start_idx];
+        while let Some(symbol_idx) = stack.pop() {
+            assembly.symbols.push(symbol_idx);
+            for number_idx in schematic.numbers_adjacent_to_symbol(symbol_idx) {
+                if visited_numbers[*number_idx] {
+                    continue;
+                }
+                visited_numbers[*number_idx] = true;
+                assembly.numbers.push(*number_idx);
+                for next_idx in schematic.symbols_adjacent_to_number(*number_idx) {
+                    if !visited_symbols[*next_idx] {
+                        visited_symbols[*next_idx] = true;
+                        stack.push(*next_idx);
+                    }
+                }
+            }
+        }
+        assembly.symbols.sort_unstable();
+        assembly.numbers.sort_unstable();
+        assemblies.push(assembly);
     }
+    assemblies
+}
 
-    gear_ratios.iter().sum()
+/// Renders the symbol to number graph in the DOT language. Symbols are drawn
+/// as boxes, numbers without any adjacent symbol are left unconnected.
+fn to_dot(schematic: &Schematic) -> String {
+    let mut dot = String::from("graph schematic {\n");
+    for (symbol_idx, symbol) in schematic.symbols.iter().enumerate() {
+        let label = match symbol.chr {
+            '"' | '\\' => format!("\\{}", symbol.chr),
+            chr => chr.to_string(),
+        };
+        writeln!(
+            dot,
+            "  s{} [shape=box, label=\"{}\\n{}:{}\"];",
+            symbol_idx,
+            label,
+            symbol.row + 1,
+            symbol.col + 1
+        )
+        .unwrap();
+    }
+    for (number_idx, number) in schematic.numbers.iter().enumerate() {
+        writeln!(
+            dot,
+            "  n{} [label=\"{}\\n{}:{}\"];",
+            number_idx,
+            number.value,
+            number.row + 1,
+            number.cols.start + 1
+        )
+        .unwrap();
+    }
+    for (symbol_idx, number_ids) in schematic.symbol_numbers.iter().enumerate() {
+        for number_idx in number_ids {
+            writeln!(dot, "  s{} -- n{};", symbol_idx, number_idx).unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
 }
 
-fn get_gear_ratio(input: &Vec<String>, row_idx: usize, match_idx: usize) -> u32 {
-    let num_cols = input[row_idx].len();
-    let mut part_nums: Vec<u32> = Vec::new();
-    if row_idx > 0 {
-        let (up_l, up_r) = get_enclosing_range_from_subrange(
-            &input[row_idx - 1],
-            match_idx.saturating_sub(1),
-            min(match_idx + 1, num_cols),
+fn print_graph_report(schematic: &Schematic) {
+    let assemblies = find_assemblies(schematic);
+    println!("Sub-assemblies: {}", assemblies.len());
+    if let Some(largest) = assemblies
+        .iter()
+        .max_by_key(|assembly| assembly.numbers.len())
+    {
+        let values: Vec<u32> = largest
+            .numbers
+            .iter()
+            .map(|number_idx| schematic.numbers[*number_idx].value)
+            .collect();
+        println!(
+            "Largest sub-assembly: {} symbols, parts {:?}",
+            largest.symbols.len(),
+            values
         );
+    }
 
-        part_nums.extend(
-            input[row_idx - 1][up_l..=up_r]
-                .split(|c: char| !c.is_numeric())
-                .filter_map(|s: &str| s.parse::<u32>().ok()),
+    if let Some((symbol_idx, symbol)) = schematic
+        .symbols
+        .iter()
+        .enumerate()
+        .max_by_key(|(symbol_idx, _)| schematic.numbers_adjacent_to_symbol(*symbol_idx).len())
+    {
+        println!(
+            "Symbol with most parts: '{}' at line {} column {} with {} parts",
+            symbol.chr,
+            symbol.row + 1,
+            symbol.col + 1,
+            schematic.numbers_adjacent_to_symbol(symbol_idx).len()
         );
     }
 
-    let (m_l, m_r) = get_enclosing_range_from_subrange(
-        &input[row_idx],
-        match_idx.saturating_sub(1),
-        min(match_idx + 1, num_cols),
+    for (number_idx, number) in schematic.numbers.iter().enumerate() {
+        let num_symbols = schematic.symbols_adjacent_to_number(number_idx).len();
+        if num_symbols > 1 {
+            println!(
+                "Shared part {} at line {} column {}: {} symbols",
+                number.value,
+                number.row + 1,
+                number.cols.start + 1,
+                num_symbols
+            );
+        }
+    }
+
+    let loose: Vec<u32> = schematic
+        .numbers
+        .iter()
+        .enumerate()
+        .filter(|(number_idx, _)| schematic.symbols_adjacent_to_number(*number_idx).is_empty())
+        .map(|(_, number)| number.value)
+        .collect();
+    println!("Numbers attached to no symbol: {} {:?}", loose.len(), loose);
+}
+
+/// Splits every input line into runs of characters sharing a highlight,
+/// based on the parsed `schematic` and the `gears` found in it.
+fn highlight_runs(
+    input: &[String],
+    schematic: &Schematic,
+    gears: &[Gear],
+) -> Vec<Vec<(Highlight, String)>> {
+    let mut symbol_gears = vec![None; schematic.symbols.len()];
+    for (gear_idx, gear) in gears.iter().enumerate() {
+        symbol_gears[gear.symbol_idx] = Some(gear_idx);
+    }
+
+    input
+        .iter()
+        .enumerate()
+        .map(|(row, line)| {
+            let mut runs: Vec<(Highlight, String)> = Vec::new();
+            for (col, chr) in line.chars().enumerate() {
+                let highlight = match schematic.cell(row, col) {
+                    Cell::Empty => Highlight::Plain,
+                    Cell::Number(number_idx) => {
+                        if schematic.symbols_adjacent_to_number(number_idx).is_empty() {
+                            Highlight::Uncounted
+                        } else {
+                            Highlight::Counted
+                        }
+                    }
+                    Cell::Symbol(symbol_idx) => match symbol_gears[symbol_idx] {
+                        Some(gear_idx) => Highlight::Gear(gear_idx),
+                        None => Highlight::Symbol,
+                    },
+                };
+                match runs.last_mut() {
+                    Some((run_highlight, text)) if *run_highlight == highlight => text.push(chr),
+                    _ => runs.push((highlight, chr.to_string())),
+                }
+            }
+            runs
+        })
+        .collect()
+}
+
+/// Renders the schematic with ANSI colours: counted part numbers green,
+/// uncounted numbers red, gears bold yellow and other symbols cyan.
+fn render_ansi(input: &[String], schematic: &Schematic, gears: &[Gear]) -> String {
+    let mut output = String::new();
+    for runs in highlight_runs(input, schematic, gears) {
+        for (highlight, text) in runs {
+            let color = match highlight {
+                Highlight::Plain => "\x1b[0m",
+                Highlight::Counted => "\x1b[32m",
+                Highlight::Uncounted => "\x1b[31m",
+                Highlight::Symbol => "\x1b[36m",
+                Highlight::Gear(_) => "\x1b[1;33m",
+            };
+            output.push_str(color);
+            output.push_str(&text);
+        }
+        output.push_str("\x1b[0m\n");
+    }
+    output
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders the schematic as HTML page using the colours of `render_ansi`.
+/// Hovering a gear shows its part numbers and ratio.
+fn render_html(input: &[String], schematic: &Schematic, gears: &[Gear]) -> String {
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n",
+        "<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Day 3 schematic</title>\n",
+        "<style>\n",
+        "body { background: #101018; color: #707070; }\n",
+        ".counted { color: #40c040; }\n",
+        ".uncounted { color: #e04040; }\n",
+        ".symbol { color: #40c0c0; }\n",
+        ".gear { color: #ffd020; font-weight: bold; cursor: help; }\n",
+        "</style>\n</head>\n<body>\n<pre>\n",
+    ));
+    for runs in highlight_runs(input, schematic, gears) {
+        for (highlight, text) in runs {
+            let text = escape_html(&text);
+            match highlight {
+                Highlight::Plain => html.push_str(&text),
+                Highlight::Counted => {
+                    write!(html, "<span class=\"counted\">{}</span>", text).unwrap()
+                }
+                Highlight::Uncounted => {
+                    write!(html, "<span class=\"uncounted\">{}</span>", text).unwrap()
+                }
+                Highlight::Symbol => {
+                    write!(html, "<span class=\"symbol\">{}</span>", text).unwrap()
+                }
+                Highlight::Gear(gear_idx) => write!(
+                    html,
+                    "<span class=\"gear\" title=\"ratio {} of parts {:?}\">{}</span>",
+                    gears[gear_idx].ratio, gears[gear_idx].part_nums, text
+                )
+                .unwrap(),
+            }
+        }
+        html.push('\n');
+    }
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+fn schematic_from(lines: &[&str]) -> Schematic {
+    let input: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+    Schematic::try_from(input.as_slice()).unwrap()
+}
+
+#[test]
+fn test_schematic() {
+    let schematic = schematic_from(&["467..114", "...*......", "..35..633."]);
+    let values: Vec<u32> = schematic
+        .numbers
+        .iter()
+        .map(|number| number.value)
+        .collect();
+    assert_eq!(values, vec![467, 114, 35, 633]);
+    assert_eq!(schematic.numbers[3].row, 2);
+    assert_eq!(schematic.numbers[3].cols, 6..9);
+    assert_eq!(schematic.cell(0, 1), Cell::Number(0));
+    assert_eq!(schematic.cell(1, 3), Cell::Symbol(0));
+    assert_eq!(schematic.cell(2, 5), Cell::Empty);
+    assert_eq!(schematic.cell(0, 20), Cell::Empty);
+    assert_eq!(schematic.neighbours(0, 0).len(), 3);
+    assert_eq!(schematic.numbers_adjacent_to_symbol(0), &[0, 2]);
+    assert_eq!(schematic.symbols_adjacent_to_number(2), &[0]);
+    assert!(schematic.symbols_adjacent_to_number(1).is_empty());
+
+    assert_eq!(
+        sum_of_part_nums(&schematic_from(&["4294967295*4294967295"])),
+        2 * u64::from(u32::MAX)
     );
+    let long: Vec<String> = vec![".".to_string(), "12345678901*1".to_string()];
+    assert_eq!(
+        Schematic::try_from(long.as_slice())
+            .err()
+            .unwrap()
+            .to_string(),
+        "Number at line 2 column 1 overflows u32."
+    );
+}
 
-    part_nums.extend(
-        input[row_idx][m_l..=m_r]
-            .split(|c: char| !c.is_numeric())
-            .filter_map(|s: &str| s.parse::<u32>().ok()),
+#[test]
+fn test_gear_rules() {
+    assert_eq!(Arity::try_from("2").unwrap(), Arity::Exactly(2));
+    assert_eq!(Arity::try_from(" 3+ ").unwrap(), Arity::AtLeast(3));
+    assert!(Arity::try_from("+").is_err());
+    assert!(Arity::try_from("two").is_err());
+    assert_eq!(Combine::try_from("product").unwrap(), Combine::Product);
+    assert_eq!(Combine::try_from("sum").unwrap(), Combine::Sum);
+    assert_eq!(Combine::try_from(" max").unwrap(), Combine::Max);
+    assert_eq!(
+        Combine::try_from("min").err().map(|err| err.to_string()),
+        Some("Unknown gear ratio operation 'min'.".to_string())
     );
 
-    if row_idx < (input.len() - 1) {
-        let (low_l, low_r) = get_enclosing_range_from_subrange(
-            &input[row_idx + 1],
-            match_idx.saturating_sub(1),
-            min(match_idx + 1, num_cols),
-        );
+    // A `*` with three part numbers and a `#` with two.
+    let schematic = schematic_from(&["12*3.#5", "..4..6."]);
+    let ratios = |symbols: &[char], arity, combine| {
+        let rule = GearRule {
+            symbols: symbols.to_vec(),
+            arity,
+            combine,
+        };
+        let gears = find_gears(&schematic, &rule).unwrap();
+        gears.iter().map(|gear| gear.ratio).collect::<Vec<_>>()
+    };
+    assert!(ratios(&['*'], Arity::Exactly(2), Combine::Product).is_empty());
+    assert_eq!(
+        ratios(&['*'], Arity::Exactly(3), Combine::Product),
+        vec![144]
+    );
+    assert_eq!(
+        ratios(&['*', '#'], Arity::AtLeast(2), Combine::Sum),
+        vec![19, 11]
+    );
+    assert_eq!(
+        ratios(&['*', '#'], Arity::AtLeast(2), Combine::Max),
+        vec![12, 6]
+    );
+    assert_eq!(ratios(&['#'], Arity::AtLeast(3), Combine::Max), vec![]);
 
-        part_nums.extend(
-            input[row_idx + 1][low_l..=low_r]
-                .split(|c: char| !c.is_numeric())
-                .filter_map(|s: &str| s.parse::<u32>().ok()),
-        );
-    }
+    // The product of four five-digit part numbers overflows u64.
+    let four_gear = GearRule {
+        arity: Arity::AtLeast(4),
+        ..GearRule::default()
+    };
+    let big = schematic_from(&["99999.99999", ".....*.....", "99999.99999"]);
+    assert!(find_gears(&big, &four_gear).is_err());
+    let sum_gear = GearRule {
+        combine: Combine::Sum,
+        ..four_gear
+    };
+    assert_eq!(find_gears(&big, &sum_gear).unwrap()[0].ratio, 4 * 99999);
+    let max_gear = || Gear {
+        symbol_idx: 0,
+        symbol: &big.symbols[0],
+        part_nums: Vec::new(),
+        ratio: u64::MAX,
+    };
+    assert!(sum_of_gear_ratios(&[max_gear(), max_gear()]).is_err());
+}
+
+#[test]
+fn test_borders_and_corners() {
+    // Numbers in all four corners sharing one gear in the middle.
+    let corners = schematic_from(&["1.2", ".*.", "3.4"]);
+    assert_eq!(sum_of_part_nums(&corners), 10);
+    assert!(find_gears(&corners, &GearRule::default())
+        .unwrap()
+        .is_empty());
+    let four_gear = GearRule {
+        arity: Arity::AtLeast(4),
+        ..GearRule::default()
+    };
+    assert_eq!(
+        sum_of_gear_ratios(&find_gears(&corners, &four_gear).unwrap()).unwrap(),
+        24
+    );
+
+    // Numbers running along every border, touching symbols only diagonally
+    // or at the ends of their span.
+    let borders = schematic_from(&[
+        "12.....34", //
+        "..#...#..",
+        "5.......6",
+        ".#.....#.",
+        "78.....90",
+    ]);
+    assert_eq!(sum_of_part_nums(&borders), 12 + 34 + 5 + 6 + 78 + 90);
+
+    // Symbols in the corners with the numbers next to them.
+    let symbol_corners = schematic_from(&["*1.1*", "2...2", ".....", "3...3", "*4.4*"]);
+    assert_eq!(sum_of_part_nums(&symbol_corners), 20);
+    assert_eq!(
+        sum_of_gear_ratios(&find_gears(&symbol_corners, &GearRule::default()).unwrap()).unwrap(),
+        2 + 2 + 12 + 12
+    );
+}
+
+#[test]
+fn test_ragged_rows() {
+    let ragged = schematic_from(&[
+        "467..114",
+        "...*",
+        "",
+        "35",
+        "#",
+        "..........633",
+        ".........#",
+        "",
+    ]);
+    assert_eq!(sum_of_part_nums(&ragged), 467 + 35 + 633);
+    assert!(find_gears(&ragged, &GearRule::default())
+        .unwrap()
+        .is_empty());
+
+    // A symbol past the end of the neighbouring rows.
+    let overhang = schematic_from(&["12", "....$", "3"]);
+    assert_eq!(sum_of_part_nums(&overhang), 0);
+
+    let empty = schematic_from(&[]);
+    assert_eq!(sum_of_part_nums(&empty), 0);
+    assert!(find_gears(&empty, &GearRule::default()).unwrap().is_empty());
+    assert_eq!(sum_of_part_nums(&schematic_from(&["", "", ""])), 0);
+}
+
+#[test]
+fn test_assemblies() {
+    let schematic = schematic_from(&["1*23#4", "......", "5.$..."]);
+    let assemblies = find_assemblies(&schematic);
+    assert_eq!(assemblies.len(), 2);
+    assert_eq!(assemblies[0].symbols, vec![0, 1]);
+    assert_eq!(assemblies[0].numbers, vec![0, 1, 2]);
+    assert!(assemblies[1].numbers.is_empty());
+    assert_eq!(schematic.symbols_adjacent_to_number(1), &[0, 1]);
+    assert!(schematic.symbols_adjacent_to_number(3).is_empty());
+    assert!(to_dot(&schematic).contains("  s1 -- n2;\n"));
+}
+
+#[test]
+fn test_render() {
+    let input: Vec<String> = ["12*3.<5", "&......", "....99."]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+    let schematic = Schematic::try_from(input.as_slice()).unwrap();
+    let gears = find_gears(&schematic, &GearRule::default()).unwrap();
 
-    if part_nums.len() == 2 {
-        return part_nums[0] * part_nums[1];
+    let ansi = render_ansi(&input, &schematic, &gears);
+    assert!(ansi.starts_with("\x1b[32m12\x1b[1;33m*\x1b[32m3\x1b[0m.\x1b[36m<\x1b[32m5\x1b[0m\n"));
+    assert!(ansi.contains("\x1b[31m99\x1b[0m.\x1b[0m\n"));
+
+    let html = render_html(&input, &schematic, &gears);
+    assert!(html.contains(concat!(
+        "<span class=\"counted\">12</span>",
+        "<span class=\"gear\" title=\"ratio 36 of parts [12, 3]\">*</span>",
+        "<span class=\"counted\">3</span>.",
+        "<span class=\"symbol\">&lt;</span>",
+        "<span class=\"counted\">5</span>\n",
+        "<span class=\"symbol\">&amp;</span>......\n",
+        "....<span class=\"uncounted\">99</span>.\n",
+    )));
+}
+
+/// Runs day 3 with command line options:
+///
+/// `--input <path>` reads the schematic from `path`,
+/// `--gear-symbols <chars>` sets the symbols that can be gears, e.g. `*#`,
+/// `--gear-arity <k|k+>` requires exactly or at least `k` adjacent part numbers,
+/// `--gear-combine <product|sum|max>` sets how part numbers form the ratio,
+/// `--list-gears` prints every gear with its part numbers and ratio,
+/// `--graph` reports sub-assemblies, shared parts and unattached numbers,
+/// `--dot <path>` writes the symbol to number graph as DOT file,
+/// `--render` prints the schematic with counted numbers and gears coloured,
+/// `--html <path>` writes the same view as HTML page with gear tooltips.
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut input_path = "data/input_day3";
+    let mut rule = GearRule::default();
+    let mut list_gears = false;
+    let mut graph = false;
+    let mut dot_path = None;
+    let mut render = false;
+    let mut html_path = None;
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--input" => input_path = next_arg(&mut args_iter, arg)?,
+            "--gear-symbols" => rule.symbols = next_arg(&mut args_iter, arg)?.chars().collect(),
+            "--gear-arity" => rule.arity = Arity::try_from(next_arg(&mut args_iter, arg)?)?,
+            "--gear-combine" => rule.combine = Combine::try_from(next_arg(&mut args_iter, arg)?)?,
+            "--list-gears" => list_gears = true,
+            "--graph" => graph = true,
+            "--dot" => dot_path = Some(next_arg(&mut args_iter, arg)?),
+            "--render" => render = true,
+            "--html" => html_path = Some(next_arg(&mut args_iter, arg)?),
+            _ => return Err(format!("Unknown day 3 option '{}'.", arg).into()),
+        }
     }
 
-    0
+    let reader = BufReader::new(File::open(input_path)?);
+    let file_input: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+    let schematic = Schematic::try_from(file_input.as_slice())?;
+    let gears = find_gears(&schematic, &rule)?;
+    println!("Sum of part IDs: {}", sum_of_part_nums(&schematic));
+    println!("Sum of gear ratios: {}", sum_of_gear_ratios(&gears)?);
+
+    if list_gears {
+        for gear in &gears {
+            println!(
+                "Gear '{}' at line {} column {}: parts {:?} ratio {}",
+                gear.symbol.chr,
+                gear.symbol.row + 1,
+                gear.symbol.col + 1,
+                gear.part_nums,
+                gear.ratio
+            );
+        }
+    }
+    if graph {
+        print_graph_report(&schematic);
+    }
+    if let Some(path) = dot_path {
+        fs::write(path, to_dot(&schematic))?;
+    }
+    if render {
+        print!("{}", render_ansi(&file_input, &schematic, &gears));
+    }
+    if let Some(path) = html_path {
+        fs::write(path, render_html(&file_input, &schematic, &gears))?;
+    }
+    Ok(())
 }
 
 pub fn print_answer() {
-    let reader = BufReader::new(File::open("data/input_day3").unwrap());
-    let file_input: Vec<String> = reader.lines().filter_map(Result::ok).collect();
-    println!("Sum of part IDs: {}", sum_of_part_nums(&file_input));
-    println!("Sum of gear ratios: {}", sum_of_gear_ratios(&file_input));
+    run(&[]).unwrap();
 }
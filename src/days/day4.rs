@@ -1,95 +1,515 @@
 // mod day2
 
 use std::cmp::min;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::collections::HashSet;
+use std::error::Error;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
-use std::iter::repeat;
+use std::mem;
+
+use super::next_arg;
+#[cfg(test)]
+use super::XorShift;
+#[cfg(test)]
+use std::time::Instant;
 
 #[derive(Debug)]
 struct Card {
-    winning: HashSet<u32>,
-    chosen: HashSet<u32>,
+    id: usize,
+    winning: NumberSet,
+    chosen: NumberSet,
+    /// Number of chosen numbers that are winning numbers.
+    matches: usize,
+}
+
+/// Set of card numbers. Sets of numbers below 128 are stored as bit mask, so
+/// intersecting two of them is a single popcount. Larger numbers fall back to
+/// a `HashSet`.
+#[derive(Debug, Clone, PartialEq)]
+enum NumberSet {
+    Mask(u128),
+    Set(HashSet<u32>),
+}
+
+/// Points a card is worth for its number of matches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scoring {
+    /// One point per match.
+    Linear,
+    /// One point for the first match, doubled for every further match.
+    Doubling,
+    /// Points follow the Fibonacci sequence 1, 2, 3, 5, 8, ...
+    Fibonacci,
+}
+
+/// Which copies a card wins. By default a card wins one copy of each of the
+/// next `matches` cards, stopping at the end of the table. With `wrap` the
+/// won cards continue at the first card, and `cap` limits the instances held
+/// of any card, discarding further copies.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct CopyRule {
+    wrap: bool,
+    cap: Option<u64>,
+}
+
+/// Outcome of playing the scratchcards, indexed like the cards.
+struct Simulation {
+    instances: Vec<u64>,
+    /// Copies won of each card, keyed by the id of the winning card.
+    sources: Vec<BTreeMap<usize, u64>>,
+}
+
+impl TryFrom<&str> for Card {
+    type Error = Box<dyn Error>;
+    fn try_from(value: &str) -> Result<Self, Box<dyn Error>> {
+        let (header, numbers) = value
+            .split_once(':')
+            .ok_or_else(|| format!("Missing ':' in card '{}'.", value.trim()))?;
+        let id = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            ["Card", id] => id.parse()?,
+            _ => return Err(format!("Invalid card header '{}'.", header.trim()).into()),
+        };
+        let (set_win, set_chosen) = numbers
+            .split_once('|')
+            .ok_or_else(|| format!("Missing '|' in card {}.", id))?;
+        let winning = set_win
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<NumberSet, _>>()?;
+        let chosen = set_chosen
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<NumberSet, _>>()?;
+        let mut card = Card {
+            id,
+            winning,
+            chosen,
+            matches: 0,
+        };
+        card.matches = card.winning.intersection_len(&card.chosen);
+        Ok(card)
+    }
 }
 
-impl Card {
-    fn new() -> Self {
-        Card {
-            winning: HashSet::new(),
-            chosen: HashSet::new(),
+impl FromIterator<u32> for NumberSet {
+    fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+        let numbers: Vec<u32> = iter.into_iter().collect();
+        if numbers.iter().all(|number| *number < u128::BITS) {
+            NumberSet::Mask(numbers.iter().fold(0, |mask, number| mask | (1 << number)))
+        } else {
+            NumberSet::Set(numbers.into_iter().collect())
         }
     }
 }
 
-fn parse_cards(lines: Vec<String>) -> Vec<Card> {
-    lines
+impl NumberSet {
+    fn contains(&self, number: u32) -> bool {
+        match self {
+            NumberSet::Mask(mask) => number < u128::BITS && mask & (1 << number) != 0,
+            NumberSet::Set(set) => set.contains(&number),
+        }
+    }
+
+    fn intersection_len(&self, other: &NumberSet) -> usize {
+        match (self, other) {
+            (NumberSet::Mask(lhs), NumberSet::Mask(rhs)) => (lhs & rhs).count_ones() as usize,
+            (NumberSet::Set(lhs), NumberSet::Set(rhs)) => lhs.intersection(rhs).count(),
+            (NumberSet::Set(set), mask) | (mask, NumberSet::Set(set)) => {
+                set.iter().filter(|number| mask.contains(**number)).count()
+            }
+        }
+    }
+}
+
+impl TryFrom<&str> for Scoring {
+    type Error = Box<dyn Error>;
+    fn try_from(value: &str) -> Result<Self, Box<dyn Error>> {
+        match value.trim() {
+            "linear" => Ok(Scoring::Linear),
+            "doubling" => Ok(Scoring::Doubling),
+            "fibonacci" => Ok(Scoring::Fibonacci),
+            _ => Err(format!("Unknown scoring '{}'.", value.trim()).into()),
+        }
+    }
+}
+
+impl Scoring {
+    fn points(&self, matches: usize) -> Option<u64> {
+        match (self, matches) {
+            (_, 0) => Some(0),
+            (Scoring::Linear, matches) => u64::try_from(matches).ok(),
+            (Scoring::Doubling, matches) => 1u64.checked_shl(u32::try_from(matches - 1).ok()?),
+            (Scoring::Fibonacci, matches) => {
+                let (mut points, mut next) = (1u64, 2u64);
+                for _ in 1..matches {
+                    (points, next) = (next, points.checked_add(next)?);
+                }
+                Some(points)
+            }
+        }
+    }
+}
+
+/// Parses all cards and orders them by id. The ids have to be contiguous,
+/// as won copies refer to the cards following the winning card.
+fn parse_cards(lines: &[String]) -> Result<Vec<Card>, Box<dyn Error>> {
+    let mut cards = lines
         .iter()
-        .filter_map(|l| match l.split_once(':') {
-            Some((_, rstr)) => Some(rstr),
-            _ => None,
-        })
-        .filter_map(|l| l.split_once('|'))
-        .map(|(set_win, set_chosen)| {
-            let mut c = Card::new();
-            c.winning.extend(
-                set_win
-                    .trim()
-                    .split_whitespace()
-                    .filter_map(|num: &str| num.parse::<u32>().ok()),
-            );
-            c.chosen.extend(
-                set_chosen
-                    .trim()
-                    .split_whitespace()
-                    .filter_map(|num: &str| num.parse::<u32>().ok()),
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Card::try_from(line.as_str()))
+        .collect::<Result<Vec<_>, _>>()?;
+    cards.sort_by_key(|card| card.id);
+
+    for pair in cards.windows(2) {
+        if pair[1].id != pair[0].id + 1 {
+            return Err(format!(
+                "Card ids are not contiguous: card {} is followed by card {}.",
+                pair[0].id, pair[1].id
+            )
+            .into());
+        }
+    }
+    Ok(cards)
+}
+
+fn get_total_points(cards: &[Card], scoring: Scoring) -> Result<u64, Box<dyn Error>> {
+    cards.iter().try_fold(0u64, |total, card| {
+        scoring
+            .points(card.matches)
+            .and_then(|points| total.checked_add(points))
+            .ok_or_else(|| "Total points overflow u64.".into())
+    })
+}
+
+/// Counts all original and won cards in a single pass. `expiring[idx]` holds
+/// the copies that stop being won at card `idx`, so the running number of
+/// copies won for the current card is a prefix sum over the earlier cards.
+fn get_num_scratchcards(cards: &[Card]) -> Result<u64, Box<dyn Error>> {
+    let overflow = || "Number of scratchcards overflows u64.";
+    let mut expiring = vec![0u64; cards.len() + 1];
+    let mut won_copies = 0u64;
+    let mut total = 0u64;
+    for (idx, card) in cards.iter().enumerate() {
+        won_copies -= expiring[idx];
+        let instances = won_copies.checked_add(1).ok_or_else(overflow)?;
+        total = total.checked_add(instances).ok_or_else(overflow)?;
+
+        let won_end = min(idx + 1 + card.matches, cards.len());
+        if won_end > idx + 1 {
+            won_copies = won_copies.checked_add(instances).ok_or_else(overflow)?;
+            expiring[won_end] = expiring[won_end]
+                .checked_add(instances)
+                .ok_or_else(overflow)?;
+        }
+    }
+    Ok(total)
+}
+
+/// Plays the scratchcards card by card under `rule`, tracking where the
+/// copies of every card came from. Copies won of cards that were already
+/// scratched are scratched in a further round over the table.
+fn simulate_scratchcards(cards: &[Card], rule: CopyRule) -> Result<Simulation, Box<dyn Error>> {
+    let overflow = || "Number of scratchcards overflows u64.";
+    let num_cards = cards.len();
+    let mut instances = vec![1u64; num_cards];
+    let mut pending = vec![1u64; num_cards];
+    let mut sources = vec![BTreeMap::new(); num_cards];
+    let mut round = 0;
+    while pending.iter().any(|count| *count > 0) {
+        // Without wrapping a single round suffices. With wrapping, a chain of
+        // won copies visiting a card twice repeats forever unless capped.
+        if rule.cap.is_none() && round > num_cards {
+            return Err("Cards keep winning copies forever, set a cap.".into());
+        }
+        round += 1;
+
+        for (idx, card) in cards.iter().enumerate() {
+            let scratched = mem::take(&mut pending[idx]);
+            if scratched == 0 {
+                continue;
+            }
+            for offset in 1..=card.matches {
+                let won_idx = match (rule.wrap, idx + offset) {
+                    (true, won_idx) => won_idx % num_cards,
+                    (false, won_idx) if won_idx < num_cards => won_idx,
+                    _ => break,
+                };
+                let won = match rule.cap {
+                    Some(cap) => min(scratched, cap.saturating_sub(instances[won_idx])),
+                    None => scratched,
+                };
+                if won == 0 {
+                    continue;
+                }
+                instances[won_idx] = instances[won_idx].checked_add(won).ok_or_else(overflow)?;
+                pending[won_idx] = pending[won_idx].checked_add(won).ok_or_else(overflow)?;
+                *sources[won_idx].entry(card.id).or_insert(0) += won;
+            }
+        }
+    }
+    Ok(Simulation { instances, sources })
+}
+
+fn print_trace(cards: &[Card], simulation: &Simulation) {
+    for (card_idx, card) in cards.iter().enumerate() {
+        let sources: Vec<String> = simulation.sources[card_idx]
+            .iter()
+            .map(|(source_id, copies)| format!("{} from card {}", copies, source_id))
+            .collect();
+        if sources.is_empty() {
+            println!("Card {}: 1 instance", card.id);
+        } else {
+            println!(
+                "Card {}: {} instances (1 original, {})",
+                card.id,
+                simulation.instances[card_idx],
+                sources.join(", ")
             );
-            c
+        }
+    }
+}
+
+#[cfg(test)]
+fn cards_with_matches(matches: impl Iterator<Item = usize>) -> Vec<Card> {
+    matches
+        .enumerate()
+        .map(|(idx, matches)| Card {
+            id: idx + 1,
+            winning: NumberSet::Mask(0),
+            chosen: NumberSet::Mask(0),
+            matches,
         })
         .collect()
 }
 
-fn get_total_points(cards: &Vec<Card>) -> u32 {
-    cards
+#[test]
+fn test_num_scratchcards_overflow() {
+    // Every card wins a copy of all following cards, doubling the count of
+    // each next card.
+    let doubling = |num_cards: usize| cards_with_matches((0..num_cards).rev());
+    assert_eq!(get_num_scratchcards(&doubling(10)).unwrap(), (1 << 10) - 1);
+    assert_eq!(get_num_scratchcards(&doubling(64)).unwrap(), u64::MAX);
+    assert!(get_num_scratchcards(&doubling(65)).is_err());
+}
+
+#[test]
+fn test_copy_rules() {
+    // Card 3 wins a copy of card 4 and, when wrapping, of card 1.
+    let cards = cards_with_matches([1, 0, 2, 0].into_iter());
+    assert_eq!(get_num_scratchcards(&cards).unwrap(), 6);
+    let simulation = simulate_scratchcards(&cards, CopyRule::default()).unwrap();
+    assert_eq!(simulation.instances, vec![1, 2, 1, 2]);
+    assert_eq!(simulation.sources[3], BTreeMap::from([(3, 1)]));
+
+    let wrap = CopyRule {
+        wrap: true,
+        cap: None,
+    };
+    let simulation = simulate_scratchcards(&cards, wrap).unwrap();
+    assert_eq!(simulation.instances, vec![2, 3, 1, 2]);
+    assert_eq!(simulation.sources[1], BTreeMap::from([(1, 2)]));
+
+    // Cards 1 and 2 win copies of each other forever.
+    let cycle = cards_with_matches([1, 1].into_iter());
+    assert!(simulate_scratchcards(&cycle, wrap).is_err());
+    let capped = CopyRule {
+        wrap: true,
+        cap: Some(5),
+    };
+    assert_eq!(
+        simulate_scratchcards(&cycle, capped).unwrap().instances,
+        vec![5, 5]
+    );
+}
+
+#[test]
+fn test_scoring() {
+    let points = |scoring: Scoring| -> Vec<u64> {
+        (0..6)
+            .map(|matches| scoring.points(matches).unwrap())
+            .collect()
+    };
+    assert_eq!(points(Scoring::Linear), vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(points(Scoring::Doubling), vec![0, 1, 2, 4, 8, 16]);
+    assert_eq!(points(Scoring::Fibonacci), vec![0, 1, 2, 3, 5, 8]);
+    assert_eq!(Scoring::Doubling.points(65), None);
+}
+
+#[test]
+fn test_number_set() {
+    let small: NumberSet = [1, 5, 99, 127].into_iter().collect();
+    let large: NumberSet = [5, 99, 128, 1000].into_iter().collect();
+    assert!(matches!(small, NumberSet::Mask(_)));
+    assert!(matches!(large, NumberSet::Set(_)));
+    assert_eq!(small.intersection_len(&small), 4);
+    assert_eq!(small.intersection_len(&large), 2);
+    assert_eq!(large.intersection_len(&small), 2);
+    assert_eq!(large.intersection_len(&large), 4);
+
+    let card = Card::try_from("Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53").unwrap();
+    assert_eq!(card.matches, 4);
+}
+
+/// Compares matching numbers with `HashSet` and with `NumberSet`.
+#[test]
+#[ignore]
+fn bench_card_matching() {
+    let mut random = XorShift::new(0x9e3779b97f4a7c15);
+    let mut next_number = || (random.next_u64() % 99) as u32 + 1;
+    // Cards shaped like the puzzle input: 10 winning and 25 chosen numbers.
+    let cards: Vec<(Vec<u32>, Vec<u32>)> = (0..1_000_000)
+        .map(|_| {
+            (
+                (0..10).map(|_| next_number()).collect(),
+                (0..25).map(|_| next_number()).collect(),
+            )
+        })
+        .collect();
+
+    let start = Instant::now();
+    let hash_set_matches: usize = cards
+        .iter()
+        .map(|(winning, chosen)| {
+            let winning: HashSet<u32> = winning.iter().copied().collect();
+            let chosen: HashSet<u32> = chosen.iter().copied().collect();
+            winning.intersection(&chosen).count()
+        })
+        .sum();
+    println!(
+        "HashSet: {} matches in {:?}",
+        hash_set_matches,
+        start.elapsed()
+    );
+
+    let start = Instant::now();
+    let number_set_matches: usize = cards
         .iter()
-        .map(|card: &Card| {
-            card.winning
-                .intersection(&card.chosen)
-                .fold(0u32, |points, _| match points {
-                    0 => 1,
-                    _ => 2 * points,
-                })
+        .map(|(winning, chosen)| {
+            let winning: NumberSet = winning.iter().copied().collect();
+            let chosen: NumberSet = chosen.iter().copied().collect();
+            winning.intersection_len(&chosen)
         })
-        .sum()
+        .sum();
+    println!(
+        "NumberSet: {} matches in {:?}",
+        number_set_matches,
+        start.elapsed()
+    );
+    assert_eq!(hash_set_matches, number_set_matches);
 }
 
-fn get_num_scratchcards(cards: &Vec<Card>) -> u32 {
-    cards
+/// Times counting scratchcards for growing numbers of cards.
+#[test]
+#[ignore]
+fn bench_num_scratchcards() {
+    let mut random = XorShift::new(0x2545f4914f6cdd1d);
+    for num_cards in [1_000_000, 2_000_000, 4_000_000, 8_000_000] {
+        // Mostly losing cards keep the number of copies within u64.
+        let cards = cards_with_matches((0..num_cards).map(|_| {
+            let seed = random.next_u64();
+            if seed.is_multiple_of(4) {
+                (seed >> 8) as usize % 4
+            } else {
+                0
+            }
+        }));
+        let start = Instant::now();
+        let total = get_num_scratchcards(&cards);
+        println!(
+            "{} cards: {:?} scratchcards in {:?}",
+            num_cards,
+            total,
+            start.elapsed()
+        );
+    }
+}
+
+#[test]
+fn test_parse_cards() {
+    let lines: Vec<String> = [
+        "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53",
+        "Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19",
+        "Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1",
+        "Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83",
+        "Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36",
+        "Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11",
+    ]
+    .iter()
+    .map(|line| line.to_string())
+    .collect();
+    let cards = parse_cards(&lines).unwrap();
+    assert_eq!(get_total_points(&cards, Scoring::Doubling).unwrap(), 13);
+    assert_eq!(get_num_scratchcards(&cards).unwrap(), 30);
+
+    let mut shuffled = lines.clone();
+    shuffled.swap(0, 4);
+    shuffled.swap(1, 3);
+    shuffled.insert(2, String::new());
+    let cards = parse_cards(&shuffled).unwrap();
+    let ids: Vec<usize> = cards.iter().map(|card| card.id).collect();
+    assert_eq!(ids, vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(get_total_points(&cards, Scoring::Doubling).unwrap(), 13);
+    assert_eq!(get_num_scratchcards(&cards).unwrap(), 30);
+
+    let parse_err = |lines: &[String]| parse_cards(lines).err().map(|err| err.to_string());
+    assert_eq!(
+        parse_err(&[&lines[..2], &lines[3..]].concat()),
+        Some("Card ids are not contiguous: card 2 is followed by card 4.".to_string())
+    );
+    assert_eq!(
+        parse_err(&[&lines[..], &lines[2..3]].concat()),
+        Some("Card ids are not contiguous: card 3 is followed by card 3.".to_string())
+    );
+}
+
+/// Runs day 4 with command line options:
+///
+/// `--input <path>` reads the cards from `path`,
+/// `--scoring <linear|doubling|fibonacci>` sets the points per number of matches,
+/// `--wrap` lets won copies continue at the first card after the last one,
+/// `--cap <n>` limits the instances held of each card to `n`,
+/// `--trace` prints for every card how many copies were won from which cards.
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut input_path = "data/input_day4";
+    let mut scoring = Scoring::Doubling;
+    let mut rule = CopyRule::default();
+    let mut trace = false;
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--input" => input_path = next_arg(&mut args_iter, arg)?,
+            "--scoring" => scoring = Scoring::try_from(next_arg(&mut args_iter, arg)?)?,
+            "--wrap" => rule.wrap = true,
+            "--cap" => rule.cap = Some(next_arg(&mut args_iter, arg)?.parse()?),
+            "--trace" => trace = true,
+            _ => return Err(format!("Unknown day 4 option '{}'.", arg).into()),
+        }
+    }
+
+    let reader = BufReader::new(File::open(input_path)?);
+    let file_input: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+    let cards = parse_cards(&file_input)?;
+    println!("Total points: {}", get_total_points(&cards, scoring)?);
+
+    if rule == CopyRule::default() && !trace {
+        println!("Part2 Num scratchcards: {}", get_num_scratchcards(&cards)?);
+        return Ok(());
+    }
+    let simulation = simulate_scratchcards(&cards, rule)?;
+    let total = simulation
+        .instances
         .iter()
-        .map(|card: &Card| card.winning.intersection(&card.chosen).count())
-        .enumerate()
-        .fold(
-            HashMap::from_iter((0..cards.len()).zip(repeat(1))),
-            |mut card_counts: HashMap<usize, u32>, (id, num_hits)| {
-                let instances = card_counts.get(&id).unwrap().clone();
-                let won_card_ids = (id..min(id + num_hits + 1, cards.len())).skip(1);
-                for i in won_card_ids {
-                    if let Some(won_count) = card_counts.get_mut(&i) {
-                        *won_count += instances;
-                    }
-                }
-                card_counts
-            },
-        )
-        .values()
-        .sum()
+        .try_fold(0u64, |total, count| total.checked_add(*count))
+        .ok_or("Number of scratchcards overflows u64.")?;
+    println!("Part2 Num scratchcards: {}", total);
+    if trace {
+        print_trace(&cards, &simulation);
+    }
+    Ok(())
 }
 
 pub fn print_answer() {
-    let reader = BufReader::new(File::open("data/input_day4").unwrap());
-    let file_input: Vec<String> = reader.lines().filter_map(Result::ok).collect();
-    let cards = parse_cards(file_input);
-    println!("Total points: {}", get_total_points(&cards));
-    println!("Part2 Num scratchcards: {}", get_num_scratchcards(&cards));
+    run(&[]).unwrap();
 }
@@ -1,38 +1,49 @@
+use std::cmp::max;
+use std::cmp::min;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::ops::Range;
+use std::slice;
+#[cfg(test)]
+use std::time::Instant;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum ResourceType {
-    Seed,
-    Soil,
-    Fertilizer,
-    Water,
-    Light,
-    Temperature,
-    Humidity,
-    Location,
-}
+use super::next_arg;
+#[cfg(test)]
+use super::XorShift;
+
+/// Name of a resource category, taken from the `X-to-Y map:` headers.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+struct ResourceType(String);
 
 impl TryFrom<&str> for ResourceType {
     type Error = &'static str;
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value.trim() {
-            "seed" => Ok(ResourceType::Seed),
-            "soil" => Ok(ResourceType::Soil),
-            "fertilizer" => Ok(ResourceType::Fertilizer),
-            "water" => Ok(ResourceType::Water),
-            "light" => Ok(ResourceType::Light),
-            "temperature" => Ok(ResourceType::Temperature),
-            "humidity" => Ok(ResourceType::Humidity),
-            "location" => Ok(ResourceType::Location),
-            _ => Err("Unable to parse unknown resource type."),
+        let name = value.trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err("Unable to parse resource type name.");
         }
+        Ok(ResourceType(name.to_string()))
+    }
+}
+
+impl fmt::Display for ResourceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 struct ResourceValue {
     id: u64,
     res_type: ResourceType,
@@ -41,10 +52,139 @@ struct ResourceValue {
 struct ResourceMapping {
     destination: ResourceType,
     source: ResourceType,
+    /// Destination and source range of every entry, in almanac order.
     mapping: Vec<(Range<u64>, Range<u64>)>,
+    by_source: EntryIndex,
+    by_destination: EntryIndex,
+}
+
+/// The source or destination ranges of a mapping's entries cut into disjoint
+/// pieces sorted by start, for finding the entry containing an id by binary
+/// search. Where ranges overlap, the entry listed first in the almanac takes
+/// precedence.
+struct EntryIndex {
+    /// Pieces of the ranges, each with the index of its entry.
+    pieces: Vec<(Range<u64>, usize)>,
+}
+
+/// The mappings of an almanac as directed graph, with an edge from the
+/// source to the destination type of every mapping.
+struct ResourceGraph {
+    mappings: Vec<ResourceMapping>,
+    /// Indices into `mappings` of the mappings leaving each type.
+    edges: HashMap<ResourceType, Vec<usize>>,
+}
+
+/// A piecewise linear function on ids. Every piece shifts the ids of its
+/// source interval by its offset, ids outside of all pieces map to themselves.
+#[derive(Debug, Clone, PartialEq)]
+struct PiecewiseMap {
+    /// Disjoint source intervals sorted by start, none with offset zero.
+    pieces: Vec<(Range<u64>, i128)>,
+}
+
+impl EntryIndex {
+    fn new(mapping: &[(Range<u64>, Range<u64>)], by_destination: bool) -> Self {
+        let side = |idx: &usize| {
+            let (dst, src) = &mapping[*idx];
+            if by_destination {
+                dst
+            } else {
+                src
+            }
+        };
+        let mut starts: Vec<usize> = (0..mapping.len()).collect();
+        starts.sort_by_key(|idx| side(idx).start);
+        let mut bounds: Vec<u64> = (0..mapping.len())
+            .flat_map(|idx| [side(&idx).start, side(&idx).end])
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        // Sweeps over the intervals between all range bounds, with the
+        // entries covering the current interval on a heap by their index.
+        let mut pieces: Vec<(Range<u64>, usize)> = Vec::new();
+        let mut covering = BinaryHeap::new();
+        let mut next_start = starts.iter().peekable();
+        for interval in bounds.windows(2) {
+            let (start, end) = (interval[0], interval[1]);
+            while let Some(idx) = next_start.next_if(|idx| side(idx).start <= start) {
+                covering.push(Reverse(*idx));
+            }
+            while let Some(Reverse(idx)) = covering.peek() {
+                if side(idx).end > start {
+                    break;
+                }
+                covering.pop();
+            }
+            if let Some(Reverse(idx)) = covering.peek() {
+                match pieces.last_mut() {
+                    Some((last, last_idx)) if last_idx == idx && last.end == start => {
+                        last.end = end
+                    }
+                    _ => pieces.push((start..end, *idx)),
+                }
+            }
+        }
+        EntryIndex { pieces }
+    }
 }
 
 impl ResourceMapping {
+    fn new(
+        source: ResourceType,
+        destination: ResourceType,
+        mapping: Vec<(Range<u64>, Range<u64>)>,
+    ) -> Self {
+        ResourceMapping {
+            by_source: EntryIndex::new(&mapping, false),
+            by_destination: EntryIndex::new(&mapping, true),
+            source,
+            destination,
+            mapping,
+        }
+    }
+
+    /// The disjoint pieces of the source ranges, or of the destination
+    /// ranges if `inverse`, in order, each as pair of the range mapped from
+    /// and the range mapped to.
+    fn pieces(&self, inverse: bool) -> impl Iterator<Item = (Range<u64>, Range<u64>)> + '_ {
+        let index = if inverse {
+            &self.by_destination
+        } else {
+            &self.by_source
+        };
+        index
+            .pieces
+            .iter()
+            .map(move |(piece, idx)| self.clip_entry(*idx, piece, inverse))
+    }
+
+    /// The part of entry `idx` mapping the ids of `piece`.
+    fn clip_entry(
+        &self,
+        idx: usize,
+        piece: &Range<u64>,
+        inverse: bool,
+    ) -> (Range<u64>, Range<u64>) {
+        let (dst, src) = &self.mapping[idx];
+        let (from, to) = if inverse { (dst, src) } else { (src, dst) };
+        let start = to.start + (piece.start - from.start);
+        (piece.clone(), start..(start + (piece.end - piece.start)))
+    }
+
+    /// Finds the piece mapping `id` like `pieces`.
+    fn find_entry(&self, id: u64, inverse: bool) -> Option<(Range<u64>, Range<u64>)> {
+        let index = if inverse {
+            &self.by_destination
+        } else {
+            &self.by_source
+        };
+        let pos = index.pieces.partition_point(|(piece, _)| piece.end <= id);
+        let (piece, idx) = index.pieces.get(pos)?;
+        (piece.start <= id).then(|| self.clip_entry(*idx, piece, inverse))
+    }
+
     fn map(&self, value: ResourceValue, inverse: bool) -> Result<ResourceValue, &str> {
         if (!inverse && (value.res_type != self.source))
             || (inverse && (value.res_type != self.destination))
@@ -52,132 +192,1409 @@ impl ResourceMapping {
             return Err("Invalid source type");
         }
 
-        if let Some(r) = self.mapping.iter().find(|range| {
-            if inverse {
-                range.0.contains(&value.id)
+        let id = match self.find_entry(value.id, inverse) {
+            Some((from, to)) => to.start + (value.id - from.start),
+            None => value.id,
+        };
+        Ok(ResourceValue {
+            id,
+            res_type: if inverse {
+                self.source.clone()
             } else {
-                range.1.contains(&value.id)
-            }
-        }) {
-            let range_src = if inverse { &r.0 } else { &r.1 };
-            let range_dst = if inverse { &r.1 } else { &r.0 };
-            Ok(ResourceValue {
-                id: range_dst.start + (value.id - range_src.start),
-                res_type: if inverse {
-                    self.source
-                } else {
-                    self.destination
-                },
-            })
-        } else {
-            Ok(ResourceValue {
-                id: value.id,
-                res_type: if inverse {
-                    self.source
-                } else {
-                    self.destination
-                },
-            })
+                self.destination.clone()
+            },
+        })
+    }
+
+    /// Maps whole ranges of ids at once. Parts of a range covered by a mapping
+    /// entry are shifted along with it, gaps between the entries are passed
+    /// through unchanged.
+    fn map_ranges(&self, ranges: &[Range<u64>], inverse: bool) -> Vec<Range<u64>> {
+        let entries: Vec<(Range<u64>, Range<u64>)> = self.pieces(inverse).collect();
+        let mut mapped = Vec::new();
+        for range in ranges {
+            let mut start = range.start;
+            for (from, to) in &entries {
+                if start >= range.end || from.start >= range.end {
+                    break;
+                }
+                if from.end <= start {
+                    continue;
+                }
+                if from.start > start {
+                    mapped.push(start..from.start);
+                    start = from.start;
+                }
+                let end = min(from.end, range.end);
+                mapped.push((to.start + (start - from.start))..(to.start + (end - from.start)));
+                start = end;
+            }
+            if start < range.end {
+                mapped.push(start..range.end);
+            }
         }
+        mapped
+    }
+
+    /// The mapping with the entries of its `PiecewiseMap`.
+    fn normalized(&self) -> ResourceMapping {
+        let entries = PiecewiseMap::from(self)
+            .pieces
+            .into_iter()
+            .map(|(src, offset)| (shift(src.start, offset)..shift(src.end, offset), src))
+            .collect();
+        ResourceMapping::new(self.source.clone(), self.destination.clone(), entries)
     }
 }
 
-fn parse_seeds(input: &Vec<String>) -> Vec<ResourceValue> {
+/// Parses the ids of the first line, e.g. `seeds: 79 14`. Their type is the
+/// singular of the line's name.
+fn parse_seeds(input: &[String]) -> Vec<ResourceValue> {
     input
         .iter()
         .take(1)
-        .flat_map(|line| {
-            line.trim_start_matches(|c: char| !c.is_whitespace())
-                .split_whitespace()
-        })
-        .filter_map(|seed_id| {
-            Some(ResourceValue {
-                id: seed_id.parse().ok()?,
-                res_type: ResourceType::Seed,
+        .filter_map(|line| line.split_once(':'))
+        .flat_map(|(name, ids)| {
+            let name = name.trim();
+            let res_type = ResourceType::try_from(name.strip_suffix('s').unwrap_or(name)).ok();
+            ids.split_whitespace().filter_map(move |seed_id| {
+                Some(ResourceValue {
+                    id: seed_id.parse().ok()?,
+                    res_type: res_type.clone()?,
+                })
             })
         })
         .collect()
 }
 
-fn parse_resource_mappings(input: &Vec<String>) -> Vec<ResourceMapping> {
-    input
+/// Parses a section header such as `seed-to-soil map:`.
+fn parse_section_header(line: &str) -> Option<(ResourceType, ResourceType)> {
+    let (l_type, r_type) = line.trim().strip_suffix(" map:")?.split_once("-to-")?;
+    Some((
+        ResourceType::try_from(l_type).ok()?,
+        ResourceType::try_from(r_type).ok()?,
+    ))
+}
+
+/// Parses a `dst src len` entry line into its destination and source range.
+/// Fails on ranges ending past `u64::MAX`.
+fn parse_entry(line: &str) -> Result<(Range<u64>, Range<u64>), IssueKind> {
+    let numbers: Vec<u64> = line
+        .split_whitespace()
+        .map(|number| number.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| IssueKind::Malformed)?;
+    let [dst_start, src_start, len] = numbers[..] else {
+        return Err(IssueKind::Malformed);
+    };
+    let dst_end = dst_start.checked_add(len).ok_or(IssueKind::Overflow)?;
+    let src_end = src_start.checked_add(len).ok_or(IssueKind::Overflow)?;
+    Ok((dst_start..dst_end, src_start..src_end))
+}
+
+/// Parses the mapping sections. Lines which are no valid header or entry are
+/// skipped, as are entries of sections with an invalid header; see
+/// `validate_almanac` for reporting them.
+fn parse_resource_mappings(input: &[String]) -> Vec<ResourceMapping> {
+    let mut sections = Vec::new();
+    let mut in_section = false;
+    for line in input.iter().skip(1).filter(|line| !line.trim().is_empty()) {
+        if line.trim_end().ends_with("map:") {
+            in_section = match parse_section_header(line) {
+                Some((source, destination)) => {
+                    sections.push((source, destination, Vec::new()));
+                    true
+                }
+                None => false,
+            };
+        } else if let (true, Some((_, _, entries)), Ok(entry)) =
+            (in_section, sections.last_mut(), parse_entry(line))
+        {
+            entries.push(entry);
+        }
+    }
+    sections
+        .into_iter()
+        .map(|(source, destination, entries)| ResourceMapping::new(source, destination, entries))
+        .collect()
+}
+
+/// A problem found in an almanac, reported with its line number.
+#[derive(Debug, PartialEq)]
+struct AlmanacIssue {
+    line: usize,
+    kind: IssueKind,
+}
+
+#[derive(Debug, PartialEq)]
+enum IssueKind {
+    Malformed,
+    EntryOutsideSection,
+    ZeroLength,
+    Overflow,
+    /// The source range overlaps the one of the entry on the given line, the
+    /// entry listed first shadows the other.
+    OverlappingSource(usize),
+    /// The destination range overlaps the one of the entry on the given
+    /// line, so the mapping is not injective.
+    OverlappingDestination(usize),
+    /// Entries map ids onto ids which the section passes through unchanged,
+    /// so the mapping is not injective.
+    PassThroughCollision,
+    /// The source type is neither the type of the ids on the first line nor
+    /// the destination of an earlier section.
+    OutOfOrder(ResourceType),
+}
+
+impl fmt::Display for AlmanacIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: ", self.line)?;
+        match &self.kind {
+            IssueKind::Malformed => write!(f, "Expected a section header or three ids."),
+            IssueKind::EntryOutsideSection => write!(f, "Entry outside of a valid section."),
+            IssueKind::ZeroLength => write!(f, "Range of length zero."),
+            IssueKind::Overflow => write!(f, "Range ends past {}.", u64::MAX),
+            IssueKind::OverlappingSource(line) => {
+                write!(f, "Source range overlaps the one on line {}.", line)
+            }
+            IssueKind::OverlappingDestination(line) => {
+                write!(f, "Destination range overlaps the one on line {}.", line)
+            }
+            IssueKind::PassThroughCollision => {
+                write!(f, "Section maps ids onto ids it passes through unchanged.")
+            }
+            IssueKind::OutOfOrder(res_type) => {
+                write!(
+                    f,
+                    "Section maps from '{}' before any section maps to it.",
+                    res_type
+                )
+            }
+        }
+    }
+}
+
+/// Reports every pair of overlapping ranges, on the line of the entry listed
+/// later.
+fn overlapping_ranges(ranges: &[(Range<u64>, usize)]) -> Vec<(usize, usize)> {
+    let mut sorted: Vec<&(Range<u64>, usize)> = ranges.iter().collect();
+    sorted.sort_by_key(|(range, _)| range.start);
+    let mut overlaps = Vec::new();
+    for (idx, (range, line)) in sorted.iter().enumerate() {
+        for (other, other_line) in sorted[idx + 1..].iter() {
+            if other.start >= range.end {
+                break;
+            }
+            overlaps.push((max(*line, *other_line), min(*line, *other_line)));
+        }
+    }
+    overlaps.sort_unstable();
+    overlaps
+}
+
+fn validate_section(
+    header_line: usize,
+    entries: &[(Range<u64>, Range<u64>, usize)],
+    issues: &mut Vec<AlmanacIssue>,
+) {
+    let sources: Vec<_> = entries
+        .iter()
+        .map(|(_, src, line)| (src.clone(), *line))
+        .collect();
+    for (line, other) in overlapping_ranges(&sources) {
+        issues.push(AlmanacIssue {
+            line,
+            kind: IssueKind::OverlappingSource(other),
+        });
+    }
+    let destinations: Vec<_> = entries
         .iter()
-        .skip(1)
-        .filter(|line| !line.is_empty())
-        .fold(Vec::new(), |mut resources, line| {
-            if line.ends_with("map:") {
-                if let Some((l_type, r_type)) =
-                    line.trim().trim_end_matches(" map:").split_once("-to-")
-                {
-                    let source_type = ResourceType::try_from(l_type).unwrap();
-                    let dest_type = ResourceType::try_from(r_type).unwrap();
-
-                    resources.push(ResourceMapping {
-                        source: source_type,
-                        destination: dest_type,
-                        mapping: Vec::new(),
+        .map(|(dst, _, line)| (dst.clone(), *line))
+        .collect();
+    for (line, other) in overlapping_ranges(&destinations) {
+        issues.push(AlmanacIssue {
+            line,
+            kind: IssueKind::OverlappingDestination(other),
+        });
+    }
+
+    // Ids outside of all source ranges map to themselves.
+    let mut covered: Vec<Range<u64>> = sources.iter().map(|(src, _)| src.clone()).collect();
+    covered.sort_by_key(|range| range.start);
+    let mut pass_through = Vec::new();
+    let mut start = 0;
+    for range in covered {
+        if start < range.start {
+            pass_through.push(start..range.start);
+        }
+        start = max(start, range.end);
+    }
+    pass_through.push(start..u64::MAX);
+    if entries.iter().any(|(dst, src, _)| {
+        !dst.is_empty()
+            && src.start != dst.start
+            && pass_through
+                .iter()
+                .any(|range| range.start < dst.end && dst.start < range.end)
+    }) {
+        issues.push(AlmanacIssue {
+            line: header_line,
+            kind: IssueKind::PassThroughCollision,
+        });
+    }
+}
+
+/// Checks the mapping sections of an almanac for entries `parse_resource_mappings`
+/// would skip or silently shadow, and for mappings which are not injective.
+fn validate_almanac(input: &[String]) -> Vec<AlmanacIssue> {
+    let mut issues = Vec::new();
+    let mut known_types: Vec<ResourceType> = parse_seeds(input)
+        .first()
+        .map(|seed| seed.res_type.clone())
+        .into_iter()
+        .collect();
+    let mut section: Option<usize> = None;
+    let mut entries = Vec::new();
+
+    for (line_idx, line) in input.iter().enumerate().skip(1) {
+        let line_num = line_idx + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.trim_end().ends_with("map:") {
+            if let Some(header_line) = section.take() {
+                validate_section(header_line, &entries, &mut issues);
+            }
+            entries.clear();
+            match parse_section_header(line) {
+                Some((source, destination)) => {
+                    if !known_types.contains(&source) {
+                        issues.push(AlmanacIssue {
+                            line: line_num,
+                            kind: IssueKind::OutOfOrder(source),
+                        });
+                    }
+                    known_types.push(destination);
+                    section = Some(line_num);
+                }
+                None => issues.push(AlmanacIssue {
+                    line: line_num,
+                    kind: IssueKind::Malformed,
+                }),
+            }
+            continue;
+        }
+
+        match parse_entry(line) {
+            Ok(_) if section.is_none() => issues.push(AlmanacIssue {
+                line: line_num,
+                kind: IssueKind::EntryOutsideSection,
+            }),
+            Ok((dst, src)) => {
+                if src.is_empty() {
+                    issues.push(AlmanacIssue {
+                        line: line_num,
+                        kind: IssueKind::ZeroLength,
                     });
                 }
-            } else {
-                let mut ranges_iter = line
-                    .trim()
-                    .split_whitespace()
-                    .filter_map(|l| l.parse::<u64>().ok());
-                let (dst_start, src_start, len) = (
-                    &ranges_iter.next().unwrap(),
-                    &ranges_iter.next().unwrap(),
-                    &ranges_iter.next().unwrap(),
-                );
+                entries.push((dst, src, line_num));
+            }
+            Err(kind) => issues.push(AlmanacIssue {
+                line: line_num,
+                kind,
+            }),
+        }
+    }
+    if let Some(header_line) = section {
+        validate_section(header_line, &entries, &mut issues);
+    }
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+/// How `format_almanac` writes an almanac.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AlmanacFormat {
+    /// The puzzle format, with the entries in almanac order.
+    Verbatim,
+    /// The puzzle format, with the entries sorted by source, touching
+    /// entries of equal offset merged and identity entries removed.
+    Normalized,
+    /// A JSON object with the entries in almanac order.
+    Json,
+}
+
+impl TryFrom<&str> for AlmanacFormat {
+    type Error = Box<dyn Error>;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "verbatim" => Ok(AlmanacFormat::Verbatim),
+            "normalized" => Ok(AlmanacFormat::Normalized),
+            "json" => Ok(AlmanacFormat::Json),
+            _ => Err(format!("Unknown almanac format '{}'.", value).into()),
+        }
+    }
+}
+
+impl fmt::Display for ResourceMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-to-{} map:", self.source, self.destination)?;
+        for (dst, src) in &self.mapping {
+            write!(f, "\n{} {} {}", dst.start, src.start, src.end - src.start)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the seeds and mappings of an almanac, see `AlmanacFormat`.
+fn format_almanac(
+    seeds: &[ResourceValue],
+    mappings: &[ResourceMapping],
+    format: AlmanacFormat,
+) -> Result<String, Box<dyn Error>> {
+    let seed_type = &seeds
+        .first()
+        .ok_or("The almanac lists no ids on its first line.")?
+        .res_type;
+    let seed_ids: Vec<String> = seeds.iter().map(|seed| seed.id.to_string()).collect();
+
+    if format == AlmanacFormat::Json {
+        let sections: Vec<String> = mappings
+            .iter()
+            .map(|res_map| {
+                let entries: Vec<String> = res_map
+                    .mapping
+                    .iter()
+                    .map(|(dst, src)| {
+                        format!(
+                            "{{\"destination\": {}, \"source\": {}, \"length\": {}}}",
+                            dst.start,
+                            src.start,
+                            src.end - src.start
+                        )
+                    })
+                    .collect();
+                format!(
+                    "    {{\n      \"source\": \"{}\",\n      \"destination\": \"{}\",\n      \"entries\": [\n        {}\n      ]\n    }}",
+                    res_map.source,
+                    res_map.destination,
+                    entries.join(",\n        ")
+                )
+            })
+            .collect();
+        // Type names consist of alphanumerics, `-` and `_` only, so they
+        // need no escaping.
+        return Ok(format!(
+            "{{\n  \"seeds\": {{\"type\": \"{}\", \"ids\": [{}]}},\n  \"maps\": [\n{}\n  ]\n}}\n",
+            seed_type,
+            seed_ids.join(", "),
+            sections.join(",\n")
+        ));
+    }
 
-                if let Some(res_map) = resources.last_mut() {
-                    res_map
-                        .mapping
-                        .push((*dst_start..(dst_start + len), *src_start..(src_start + len)));
+    let mut sections = vec![format!("{}s: {}", seed_type, seed_ids.join(" "))];
+    for res_map in mappings {
+        sections.push(match format {
+            AlmanacFormat::Normalized => res_map.normalized().to_string(),
+            _ => res_map.to_string(),
+        });
+    }
+    Ok(sections.join("\n\n") + "\n")
+}
+
+fn shift(id: u64, offset: i128) -> u64 {
+    (i128::from(id) + offset) as u64
+}
+
+impl From<&ResourceMapping> for PiecewiseMap {
+    /// Where source ranges overlap, the entry listed first takes precedence.
+    fn from(res_map: &ResourceMapping) -> Self {
+        let mut pieces: Vec<(Range<u64>, i128)> = Vec::new();
+        for (src, dst) in res_map.pieces(false) {
+            let offset = i128::from(dst.start) - i128::from(src.start);
+            let start = max(src.start, pieces.last().map_or(0, |(last, _)| last.end));
+            if start < src.end {
+                pieces.push((start..src.end, offset));
+            }
+        }
+        PiecewiseMap::normalized(pieces)
+    }
+}
+
+impl PiecewiseMap {
+    /// Drops identity pieces and merges touching pieces of equal offset.
+    fn normalized(pieces: Vec<(Range<u64>, i128)>) -> Self {
+        let mut merged: Vec<(Range<u64>, i128)> = Vec::new();
+        for (range, offset) in pieces {
+            if offset == 0 || range.is_empty() {
+                continue;
+            }
+            match merged.last_mut() {
+                Some((last, last_offset)) if last.end == range.start && *last_offset == offset => {
+                    last.end = range.end
+                }
+                _ => merged.push((range, offset)),
+            }
+        }
+        PiecewiseMap { pieces: merged }
+    }
+
+    /// All pieces together with the identity mapped gaps between them,
+    /// covering every id.
+    fn segments(&self) -> Vec<(Range<u64>, i128)> {
+        let mut segments = Vec::new();
+        let mut start = 0;
+        for (range, offset) in &self.pieces {
+            if start < range.start {
+                segments.push((start..range.start, 0));
+            }
+            segments.push((range.clone(), *offset));
+            start = range.end;
+        }
+        if start < u64::MAX {
+            segments.push((start..u64::MAX, 0));
+        }
+        segments
+    }
+
+    fn apply(&self, id: u64) -> u64 {
+        let idx = self.pieces.partition_point(|(range, _)| range.end <= id);
+        match self.pieces.get(idx) {
+            Some((range, offset)) if range.contains(&id) => shift(id, *offset),
+            _ => id,
+        }
+    }
+
+    /// The function mapping an id first by `self` and then by `next`.
+    fn then(&self, next: &PiecewiseMap) -> PiecewiseMap {
+        let next_segments = next.segments();
+        let mut pieces = Vec::new();
+        for (range, offset) in self.segments() {
+            let image = shift(range.start, offset)..shift(range.end, offset);
+            let first_idx =
+                next_segments.partition_point(|(segment, _)| segment.end <= image.start);
+            for (segment, next_offset) in &next_segments[first_idx..] {
+                if segment.start >= image.end {
+                    break;
+                }
+                let start = max(segment.start, image.start);
+                let end = min(segment.end, image.end);
+                pieces.push((
+                    shift(start, -offset)..shift(end, -offset),
+                    offset + next_offset,
+                ));
+            }
+        }
+        PiecewiseMap::normalized(pieces)
+    }
+
+    /// Pre-images of `ranges`, one range per segment mapping into them. Each
+    /// returned range is mapped by a single offset.
+    fn preimage_parts(&self, ranges: &[Range<u64>]) -> Vec<Range<u64>> {
+        let mut parts = Vec::new();
+        for (segment, offset) in self.segments() {
+            let image = shift(segment.start, offset)..shift(segment.end, offset);
+            for range in ranges {
+                let start = max(image.start, range.start);
+                let end = min(image.end, range.end);
+                if start < end {
+                    parts.push(shift(start, -offset)..shift(end, -offset));
                 }
             }
-            resources
+        }
+        parts
+    }
+
+    /// All ids mapping into `ranges`, as sorted disjoint ranges.
+    fn preimage(&self, ranges: &[Range<u64>]) -> Vec<Range<u64>> {
+        let mut parts = self.preimage_parts(ranges);
+        parts.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<u64>> = Vec::new();
+        for range in parts {
+            match merged.last_mut() {
+                Some(last) if last.end >= range.start => last.end = max(last.end, range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+
+    /// Searches the image ids upward from zero and returns the first one with
+    /// a pre-image in `sources`. The ids are visited in intervals between the
+    /// bounds of the segment images, where every segment maps either all or
+    /// none of the ids.
+    fn search_upward(&self, sources: &[Range<u64>]) -> Option<u64> {
+        let segments = self.segments();
+        let mut bounds: Vec<u64> = segments
+            .iter()
+            .flat_map(|(segment, offset)| {
+                [shift(segment.start, *offset), shift(segment.end, *offset)]
+            })
+            .chain([0])
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        for interval in bounds.windows(2) {
+            let found = self
+                .preimage_parts(slice::from_ref(&(interval[0]..interval[1])))
+                .iter()
+                .flat_map(|part| {
+                    sources
+                        .iter()
+                        .filter(|source| source.start < part.end && part.start < source.end)
+                        .map(|source| self.apply(max(source.start, part.start)))
+                })
+                .min();
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+
+    /// The inverse function, if every id has exactly one pre-image.
+    fn invert(&self) -> Result<PiecewiseMap, Box<dyn Error>> {
+        let mut images: Vec<(Range<u64>, i128)> = self
+            .segments()
+            .into_iter()
+            .map(|(range, offset)| {
+                (
+                    shift(range.start, offset)..shift(range.end, offset),
+                    -offset,
+                )
+            })
+            .collect();
+        images.sort_by_key(|(image, _)| image.start);
+
+        let mut expected_start = 0;
+        for (image, _) in &images {
+            match image.start.cmp(&expected_start) {
+                std::cmp::Ordering::Less => {
+                    return Err(format!(
+                        "Not invertible, ids from {} on have several pre-images.",
+                        image.start
+                    )
+                    .into())
+                }
+                std::cmp::Ordering::Greater => {
+                    return Err(format!(
+                        "Not invertible, ids {}..{} have no pre-image.",
+                        expected_start, image.start
+                    )
+                    .into())
+                }
+                std::cmp::Ordering::Equal => expected_start = image.end,
+            }
+        }
+        Ok(PiecewiseMap::normalized(images))
+    }
+}
+
+impl fmt::Display for PiecewiseMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (range, offset) in &self.pieces {
+            writeln!(
+                f,
+                "{}..{} -> {}..{} ({:+})",
+                range.start,
+                range.end,
+                shift(range.start, *offset),
+                shift(range.end, *offset),
+                offset
+            )?;
+        }
+        write!(f, "everything else maps to itself")
+    }
+}
+
+impl From<Vec<ResourceMapping>> for ResourceGraph {
+    fn from(mappings: Vec<ResourceMapping>) -> Self {
+        let mut edges: HashMap<ResourceType, Vec<usize>> = HashMap::new();
+        for (idx, mapping) in mappings.iter().enumerate() {
+            edges.entry(mapping.source.clone()).or_default().push(idx);
+        }
+        ResourceGraph { mappings, edges }
+    }
+}
+
+fn format_path(path: &[&ResourceMapping]) -> String {
+    path.iter()
+        .map(|mapping| mapping.source.to_string())
+        .chain(path.last().map(|mapping| mapping.destination.to_string()))
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+impl ResourceGraph {
+    /// Finds the chain of mappings converting `source` ids into `target` ids.
+    /// Fails if there is no such chain, if there are several, or if a cycle
+    /// lies on a path from `source` towards `target`.
+    fn find_path(
+        &self,
+        source: &ResourceType,
+        target: &ResourceType,
+    ) -> Result<Vec<&ResourceMapping>, Box<dyn Error>> {
+        let mut paths = Vec::new();
+        if source != target {
+            let reaching = self.types_reaching(target);
+            let mut on_path = vec![source.clone()];
+            self.search_paths(target, &reaching, &mut Vec::new(), &mut on_path, &mut paths)?;
+        } else {
+            paths.push(Vec::new());
+        }
+
+        let to_mappings = |path: &Vec<usize>| -> Vec<&ResourceMapping> {
+            path.iter().map(|idx| &self.mappings[*idx]).collect()
+        };
+        match paths.as_slice() {
+            [] => Err(format!("No chain of mappings from {} to {}.", source, target).into()),
+            [path] => Ok(to_mappings(path)),
+            [first, second, ..] => Err(format!(
+                "Ambiguous mappings from {} to {}: {} or {}.",
+                source,
+                target,
+                format_path(&to_mappings(first)),
+                format_path(&to_mappings(second))
+            )
+            .into()),
+        }
+    }
+
+    /// The types with a chain of mappings to `target`, including `target`.
+    fn types_reaching(&self, target: &ResourceType) -> HashSet<ResourceType> {
+        let mut reaching = HashSet::from([target.clone()]);
+        loop {
+            let num_reaching = reaching.len();
+            for mapping in &self.mappings {
+                if reaching.contains(&mapping.destination) {
+                    reaching.insert(mapping.source.clone());
+                }
+            }
+            if reaching.len() == num_reaching {
+                return reaching;
+            }
+        }
+    }
+
+    /// Depth first search from the last type of `on_path`, collecting up to
+    /// two paths to `target`. Only follows mappings into `reaching` types, so
+    /// cycles that can not lead to `target` are skipped.
+    fn search_paths(
+        &self,
+        target: &ResourceType,
+        reaching: &HashSet<ResourceType>,
+        path: &mut Vec<usize>,
+        on_path: &mut Vec<ResourceType>,
+        paths: &mut Vec<Vec<usize>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let cur_type = on_path[on_path.len() - 1].clone();
+        for idx in self.edges.get(&cur_type).into_iter().flatten() {
+            let next_type = &self.mappings[*idx].destination;
+            if !reaching.contains(next_type) {
+                continue;
+            }
+            if let Some(cycle_start) = on_path.iter().position(|res_type| res_type == next_type) {
+                let cycle: Vec<String> = on_path[cycle_start..]
+                    .iter()
+                    .chain([next_type])
+                    .map(ResourceType::to_string)
+                    .collect();
+                return Err(format!("Cycle in almanac: {}.", cycle.join(" -> ")).into());
+            }
+
+            path.push(*idx);
+            if next_type == target {
+                paths.push(path.clone());
+            } else {
+                on_path.push(next_type.clone());
+                self.search_paths(target, reaching, path, on_path, paths)?;
+                on_path.pop();
+            }
+            path.pop();
+            if paths.len() > 1 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Composes a chain of mappings into a single function.
+fn compose_mappings(path: &[&ResourceMapping]) -> PiecewiseMap {
+    path.iter()
+        .fold(PiecewiseMap { pieces: Vec::new() }, |composed, mapping| {
+            composed.then(&PiecewiseMap::from(*mapping))
         })
 }
 
-fn get_mapping(
-    res_map: &[ResourceMapping],
-    input_val: &ResourceValue,
-    target_type: ResourceType,
-) -> ResourceValue {
-    let mut cur_val: ResourceValue = input_val.clone();
+/// Prints the id of `value` after every mapping of the chain.
+fn trace_value(path: &[&ResourceMapping], value: ResourceValue) -> Result<(), Box<dyn Error>> {
+    let mut cur_val = value;
+    print!("{} {}", cur_val.res_type, cur_val.id);
+    for mapping in path {
+        cur_val = mapping.map(cur_val, false)?;
+        print!(" -> {} {}", cur_val.res_type, cur_val.id);
+    }
+    println!();
+    Ok(())
+}
 
-    while let Some(mapping) = res_map.iter().find(|map| map.source == cur_val.res_type) {
-        cur_val = mapping.map(cur_val, false).unwrap();
-        if cur_val.res_type == target_type {
-            break;
+/// Seed ids of part 2, where the seed line holds pairs of range start and
+/// range length.
+fn parse_seed_ranges(seeds: &[ResourceValue]) -> Result<Vec<Range<u64>>, Box<dyn Error>> {
+    seeds
+        .chunks_exact(2)
+        .map(|pair| {
+            let end = pair[0].id.checked_add(pair[1].id).ok_or_else(|| {
+                format!(
+                    "Seed range {} {} ends past {}.",
+                    pair[0].id,
+                    pair[1].id,
+                    u64::MAX
+                )
+            })?;
+            Ok(pair[0].id..end)
+        })
+        .collect()
+}
+
+/// Maps all `ranges` of ids along a chain of mappings.
+fn get_range_mapping(path: &[&ResourceMapping], ranges: &[Range<u64>]) -> Vec<Range<u64>> {
+    path.iter().fold(ranges.to_vec(), |cur_ranges, mapping| {
+        mapping.map_ranges(&cur_ranges, false)
+    })
+}
+
+/// Parses a single id such as `46` or a range of ids such as `46..56`.
+fn parse_id_range(value: &str) -> Result<Range<u64>, Box<dyn Error>> {
+    match value.split_once("..") {
+        Some((start, end)) => {
+            let (start, end): (u64, u64) = (start.trim().parse()?, end.trim().parse()?);
+            if start >= end {
+                return Err(format!("Id range {}..{} is empty.", start, end).into());
+            }
+            Ok(start..end)
+        }
+        None => {
+            let id: u64 = value.trim().parse()?;
+            let end = id
+                .checked_add(1)
+                .ok_or_else(|| format!("Id {} can not be looked up, use a range.", id))?;
+            Ok(id..end)
         }
     }
-    cur_val
 }
 
-pub fn print_answer() {
-    let reader = BufReader::new(File::open("data/input_day5").unwrap());
-    let input_lines: Vec<String> = reader.lines().filter_map(Result::ok).collect();
-    let seeds = parse_seeds(&input_lines);
-    let resource_maps = parse_resource_mappings(&input_lines);
+fn format_ranges(ranges: &[Range<u64>]) -> String {
+    ranges
+        .iter()
+        .map(|range| match range.end.checked_sub(range.start) {
+            Some(1) => range.start.to_string(),
+            _ => format!("{}..{}", range.start, range.end),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+const EXAMPLE_ALMANAC: &str = "seeds: 79 14 55 13
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
+
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4
+
+water-to-light map:
+88 18 7
+18 25 70
+
+light-to-temperature map:
+45 77 23
+81 45 19
+68 64 13
+
+temperature-to-humidity map:
+0 69 1
+1 0 69
+
+humidity-to-location map:
+60 56 37
+56 93 4";
+
+#[cfg(test)]
+fn example_lines() -> Vec<String> {
+    EXAMPLE_ALMANAC.lines().map(String::from).collect()
+}
+
+#[cfg(test)]
+fn example_path(graph: &ResourceGraph) -> Vec<&ResourceMapping> {
+    let seed = ResourceType::try_from("seed").unwrap();
+    let location = ResourceType::try_from("location").unwrap();
+    graph.find_path(&seed, &location).unwrap()
+}
+
+#[test]
+fn test_seed_ranges() {
+    let lines = example_lines();
+    let seeds = parse_seeds(&lines);
+    // Only one plural s is dropped from the type name.
+    let glass = parse_seeds(&["glasss: 4".to_string()]);
+    assert_eq!(glass[0].res_type.to_string(), "glass");
+    let fish = parse_seeds(&["fish: 4".to_string()]);
+    assert_eq!(fish[0].res_type.to_string(), "fish");
+    let graph = ResourceGraph::from(parse_resource_mappings(&lines));
+    let path = example_path(&graph);
+    let locations = get_range_mapping(&path, &parse_seed_ranges(&seeds).unwrap());
+    assert_eq!(locations.iter().map(|range| range.start).min(), Some(46));
+    // Mapping ranges neither loses nor gains ids.
+    assert_eq!(
+        locations
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum::<u64>(),
+        14 + 13
+    );
+
+    let seed = ResourceType::try_from("seed").unwrap();
+    let overflowing: Vec<ResourceValue> = [u64::MAX, 5, 1, 1]
+        .iter()
+        .map(|id| ResourceValue {
+            id: *id,
+            res_type: seed.clone(),
+        })
+        .collect();
+    assert_eq!(
+        parse_seed_ranges(&overflowing).unwrap_err().to_string(),
+        format!("Seed range {} 5 ends past {}.", u64::MAX, u64::MAX)
+    );
+    let seed_to_location = compose_mappings(&path);
+    for seed in 79..93 {
+        let location = seed_to_location.apply(seed);
+        assert!(locations.iter().any(|range| range.contains(&location)));
+    }
+}
+
+#[test]
+fn test_compose_mappings() {
+    let lines = example_lines();
+    let graph = ResourceGraph::from(parse_resource_mappings(&lines));
+    let seed_to_location = compose_mappings(&example_path(&graph));
+    let locations: Vec<u64> = [79, 14, 55, 13]
+        .iter()
+        .map(|seed| seed_to_location.apply(*seed))
+        .collect();
+    assert_eq!(locations, vec![82, 43, 86, 35]);
+
+    let location_to_seed = seed_to_location.invert().unwrap();
+    for seed in 0..200 {
+        assert_eq!(location_to_seed.apply(seed_to_location.apply(seed)), seed);
+    }
+    assert_eq!(location_to_seed.invert().unwrap(), seed_to_location);
+
+    // Both 0..2 and 10..12 map to 10..12.
+    let squashing = PiecewiseMap {
+        pieces: vec![(0..2, 10)],
+    };
+    assert!(squashing.invert().is_err());
+}
+
+#[test]
+fn test_find_path() {
+    let graph_from = |almanac: &str| {
+        let lines: Vec<String> = almanac.lines().map(String::from).collect();
+        ResourceGraph::from(parse_resource_mappings(&lines))
+    };
+    let res_type = |name: &str| ResourceType::try_from(name).unwrap();
+
+    let graph = graph_from("seeds: 1\nseed-to-soil map:\nsoil-to-water map:\nwater-to-light map:");
+    let path = graph
+        .find_path(&res_type("seed"), &res_type("light"))
+        .unwrap();
+    assert_eq!(format_path(&path), "seed -> soil -> water -> light");
+    assert_eq!(
+        graph
+            .find_path(&res_type("soil"), &res_type("soil"))
+            .unwrap()
+            .len(),
+        0
+    );
+    assert!(graph
+        .find_path(&res_type("light"), &res_type("seed"))
+        .is_err());
+
+    let branching =
+        graph_from("seeds: 1\nseed-to-soil map:\nsoil-to-water map:\nseed-to-water map:");
+    assert!(branching
+        .find_path(&res_type("seed"), &res_type("soil"))
+        .is_ok());
+    assert!(branching
+        .find_path(&res_type("seed"), &res_type("water"))
+        .is_err());
+
+    // A cycle behind the target does not matter, one on the way to it would
+    // allow infinitely many paths.
+    let cyclic = graph_from(concat!(
+        "seeds: 1\nseed-to-soil map:\nsoil-to-water map:\n",
+        "water-to-soil map:\nsoil-to-light map:"
+    ));
+    assert!(cyclic
+        .find_path(&res_type("seed"), &res_type("water"))
+        .is_ok());
+    assert!(cyclic
+        .find_path(&res_type("seed"), &res_type("light"))
+        .is_err());
+    // Nor does one that is reachable from the source but not on the way.
+    let dead_end = graph_from(concat!(
+        "seeds: 1\nseed-to-soil map:\nsoil-to-water map:\n",
+        "water-to-soil map:\nseed-to-light map:"
+    ));
+    let path = dead_end
+        .find_path(&res_type("seed"), &res_type("light"))
+        .unwrap();
+    assert_eq!(format_path(&path), "seed -> light");
+}
+
+#[test]
+fn test_reverse_lookup() {
+    let lines = example_lines();
+    let graph = ResourceGraph::from(parse_resource_mappings(&lines));
+    let seed_to_location = compose_mappings(&example_path(&graph));
+    assert_eq!(
+        seed_to_location.preimage(slice::from_ref(&(46..47))),
+        vec![82..83]
+    );
+    assert_eq!(
+        seed_to_location.preimage(&[82..83, 35..36]),
+        vec![13..14, 79..80]
+    );
+
+    let seed_ranges = parse_seed_ranges(&parse_seeds(&lines)).unwrap();
+    assert_eq!(seed_to_location.search_upward(&seed_ranges), Some(46));
+    assert_eq!(seed_to_location.search_upward(&[]), None);
+    assert_eq!(parse_id_range("46").unwrap(), 46..47);
+    assert_eq!(parse_id_range("46..56").unwrap(), 46..56);
+    assert!(parse_id_range(&u64::MAX.to_string()).is_err());
+    assert_eq!(
+        parse_id_range("5..3").unwrap_err().to_string(),
+        "Id range 5..3 is empty."
+    );
+    assert!(parse_id_range("5..5").is_err());
+    let reversed = Range { start: 5, end: 3 };
+    assert_eq!(
+        format_ranges(&[46..47, 46..56, reversed]),
+        "46, 46..56, 5..3"
+    );
+
+    // Both 0..2 and 10..12 map to 10..12, nothing maps to 0..2.
+    let squashing = PiecewiseMap {
+        pieces: vec![(0..2, 10)],
+    };
+    assert_eq!(squashing.preimage(slice::from_ref(&(0..12))), vec![0..12]);
+    assert_eq!(
+        squashing.preimage(slice::from_ref(&(0..11))),
+        vec![0..1, 2..11]
+    );
+    assert_eq!(squashing.search_upward(slice::from_ref(&(1..2))), Some(11));
+}
+
+#[test]
+fn test_validate_almanac() {
+    assert_eq!(validate_almanac(&example_lines()), vec![]);
+
+    let lines: Vec<String> = [
+        "seeds: 1 2",
+        "",
+        "soil-to-fertilizer map:",
+        "0 0 1",
+        "seed-to-soil map:",
+        "10 0 5",
+        "20 3 4",
+        "12 30 2",
+        "40 50 0",
+        "0 18446744073709551615 2",
+        "1 2",
+        "seed-to map:",
+        "5 5 5",
+    ]
+    .iter()
+    .map(|line| line.to_string())
+    .collect();
+    let issues: Vec<String> = validate_almanac(&lines)
+        .iter()
+        .map(|issue| issue.to_string())
+        .collect();
+    assert_eq!(
+        issues,
+        vec![
+            "Line 3: Section maps from 'soil' before any section maps to it.",
+            "Line 5: Section maps ids onto ids it passes through unchanged.",
+            "Line 7: Source range overlaps the one on line 6.",
+            "Line 8: Destination range overlaps the one on line 6.",
+            "Line 9: Range of length zero.",
+            "Line 10: Range ends past 18446744073709551615.",
+            "Line 11: Expected a section header or three ids.",
+            "Line 12: Expected a section header or three ids.",
+            "Line 13: Entry outside of a valid section.",
+        ]
+    );
+
+    // Skipped lines do not end up in the parsed mappings.
+    let mappings = parse_resource_mappings(&lines);
+    assert_eq!(mappings.len(), 2);
+    assert_eq!(mappings[1].mapping.len(), 4);
+
+    // An empty destination range collides with nothing.
+    let zero_length: Vec<String> = ["seeds: 1", "seed-to-soil map:", "40 50 0"]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+    let issues: Vec<String> = validate_almanac(&zero_length)
+        .iter()
+        .map(|issue| issue.to_string())
+        .collect();
+    assert_eq!(issues, vec!["Line 3: Range of length zero."]);
+}
+
+#[test]
+fn test_find_entry() {
+    let seed = ResourceType::try_from("seed").unwrap();
+    let soil = ResourceType::try_from("soil").unwrap();
+    // Listed out of order, with the source ranges 10..20 and 15..30 overlapping.
+    let res_map = ResourceMapping::new(
+        seed.clone(),
+        soil.clone(),
+        vec![(100..115, 15..30), (0..5, 40..45), (50..60, 10..20)],
+    );
+    let map_id = |id, inverse| {
+        let res_type = if inverse { &soil } else { &seed };
+        let value = ResourceValue {
+            id,
+            res_type: res_type.clone(),
+        };
+        res_map.map(value, inverse).unwrap().id
+    };
+    assert_eq!(map_id(9, false), 9);
+    assert_eq!(map_id(10, false), 50);
+    assert_eq!(map_id(14, false), 54);
+    // The entry listed first maps the overlap.
+    assert_eq!(map_id(15, false), 100);
+    assert_eq!(map_id(19, false), 104);
+    assert_eq!(map_id(20, false), 105);
+    assert_eq!(map_id(30, false), 30);
+    assert_eq!(map_id(44, false), 4);
+    assert_eq!(map_id(45, false), 45);
+    assert_eq!(map_id(4, true), 44);
+    assert_eq!(map_id(59, true), 19);
+    assert_eq!(map_id(100, true), 15);
+    assert_eq!(map_id(115, true), 115);
+
+    // The composed map and range mapping agree on the overlap.
+    let piecewise = PiecewiseMap::from(&res_map);
+    for id in 0..50 {
+        assert_eq!(piecewise.apply(id), map_id(id, false));
+        let mapped = res_map.map_ranges(slice::from_ref(&(id..(id + 1))), false);
+        assert_eq!(mapped, vec![map_id(id, false)..(map_id(id, false) + 1)]);
+    }
+}
+
+/// Generates an almanac of `sections` sections with `entries` entries each,
+/// every section shuffling the ids below 2^32 in pieces.
+#[cfg(test)]
+fn generate_almanac(sections: usize, entries: usize, seed: u64) -> Vec<String> {
+    let mut random = XorShift::new(seed);
+    let mut next_random = || random.next_u64();
+    let mut lines = vec!["t0s: 79 14 55 13".to_string(), String::new()];
+    for section in 0..sections {
+        lines.push(format!("t{}-to-t{} map:", section, section + 1));
+        let mut bounds: Vec<u64> = (1..entries).map(|_| next_random() % (1 << 32)).collect();
+        bounds.extend([0, 1 << 32]);
+        bounds.sort_unstable();
+        bounds.dedup();
+        let mut pieces: Vec<Range<u64>> = bounds.windows(2).map(|w| w[0]..w[1]).collect();
+        for idx in (1..pieces.len()).rev() {
+            pieces.swap(idx, next_random() as usize % (idx + 1));
+        }
+        let mut dst_start = 0;
+        for src in pieces {
+            lines.push(format!(
+                "{} {} {}",
+                dst_start,
+                src.start,
+                src.end - src.start
+            ));
+            dst_start += src.end - src.start;
+        }
+        lines.push(String::new());
+    }
+    lines
+}
+
+/// Compares the lookups of a large generated almanac against a linear search.
+#[test]
+#[ignore]
+fn bench_mapping_lookup() {
+    let lines = generate_almanac(7, 5_000, 0x9e3779b97f4a7c15);
+    let start = Instant::now();
+    let mappings = parse_resource_mappings(&lines);
+    println!(
+        "Parsed {} sections in {:?}",
+        mappings.len(),
+        start.elapsed()
+    );
+    assert_eq!(validate_almanac(&lines), vec![]);
+
+    let ids: Vec<u64> = (0..20_000u64).map(|id| id * 214_748).collect();
+    let start = Instant::now();
+    let linear: u64 = ids
+        .iter()
+        .map(|id| {
+            mappings.iter().fold(*id, |id, res_map| {
+                match res_map.mapping.iter().find(|(_, src)| src.contains(&id)) {
+                    Some((dst, src)) => dst.start + (id - src.start),
+                    None => id,
+                }
+            })
+        })
+        .min()
+        .unwrap();
+    println!("Linear search: min {} in {:?}", linear, start.elapsed());
+
+    let start = Instant::now();
+    let binary: u64 = ids
+        .iter()
+        .map(|id| {
+            mappings.iter().fold(*id, |id, res_map| {
+                let value = ResourceValue {
+                    id,
+                    res_type: res_map.source.clone(),
+                };
+                res_map.map(value, false).unwrap().id
+            })
+        })
+        .min()
+        .unwrap();
+    println!("Binary search: min {} in {:?}", binary, start.elapsed());
+    assert_eq!(linear, binary);
+
+    let start = Instant::now();
+    let round_trips = ids
+        .iter()
+        .filter(|id| {
+            let forward = mappings.iter().fold(**id, |id, res_map| {
+                res_map
+                    .find_entry(id, false)
+                    .map_or(id, |(from, to)| to.start + (id - from.start))
+            });
+            let back = mappings.iter().rev().fold(forward, |id, res_map| {
+                res_map
+                    .find_entry(id, true)
+                    .map_or(id, |(from, to)| to.start + (id - from.start))
+            });
+            back == **id
+        })
+        .count();
+    println!(
+        "Round trips: {} of {} in {:?}",
+        round_trips,
+        ids.len(),
+        start.elapsed()
+    );
+    assert_eq!(round_trips, ids.len());
+}
+
+#[test]
+fn test_format_almanac() {
+    let lines = example_lines();
+    let seeds = parse_seeds(&lines);
+    let mappings = parse_resource_mappings(&lines);
+    let verbatim = format_almanac(&seeds, &mappings, AlmanacFormat::Verbatim).unwrap();
+    assert_eq!(verbatim, format!("{}\n", EXAMPLE_ALMANAC));
+
+    let normalized = format_almanac(&seeds, &mappings, AlmanacFormat::Normalized).unwrap();
+    let normalized_lines: Vec<String> = normalized.lines().map(String::from).collect();
+    assert!(normalized.starts_with("seeds: 79 14 55 13\n\nseed-to-soil map:\n52 50 48\n50 98 2\n"));
+    assert_eq!(validate_almanac(&normalized_lines), vec![]);
+    let graph = ResourceGraph::from(parse_resource_mappings(&normalized_lines));
+    assert_eq!(
+        compose_mappings(&example_path(&graph)),
+        compose_mappings(&example_path(&ResourceGraph::from(mappings)))
+    );
+
+    let seed = ResourceType::try_from("seed").unwrap();
+    let soil = ResourceType::try_from("soil").unwrap();
+    let mappings = vec![ResourceMapping::new(
+        seed.clone(),
+        soil,
+        vec![
+            (0..5, 10..15),
+            (30..35, 30..35),
+            (5..10, 15..20),
+            (10..15, 20..25),
+        ],
+    )];
+    let seeds = vec![ResourceValue {
+        id: 7,
+        res_type: seed,
+    }];
+    assert_eq!(
+        format_almanac(&seeds, &mappings, AlmanacFormat::Normalized).unwrap(),
+        "seeds: 7\n\nseed-to-soil map:\n0 10 15\n"
+    );
+    assert_eq!(
+        format_almanac(&seeds, &mappings[..0], AlmanacFormat::Json).unwrap(),
+        "{\n  \"seeds\": {\"type\": \"seed\", \"ids\": [7]},\n  \"maps\": [\n\n  ]\n}\n"
+    );
+}
+
+/// Runs day 5 with command line options:
+///
+/// `--input <path>` reads the almanac from `path`,
+/// `--from <type>` treats the ids of the first line as ids of `type`,
+/// `--to <type>` maps the ids to `type` instead of location,
+/// `--print-map` prints the composed function between the two types,
+/// `--print-inverse` prints the inverse of the composed function,
+/// `--trace-seed <id>` prints the id after every mapping step,
+/// `--reverse <id|start..end>` prints all ids mapping to the given target ids,
+/// `--search-upward` solves part 2 by searching the target ids upward from
+/// zero for the first one mapped to from one of the seed ranges.
+/// `--write-almanac <verbatim|normalized|json>` only writes the almanac back
+/// out in the given format,
+/// `--lenient` reports problems found in the almanac as warnings instead of
+/// failing, skipping the lines which can not be parsed.
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut input_path = "data/input_day5";
+    let mut source_type = None;
+    let mut target_type = ResourceType::try_from("location")?;
+    let mut print_map = false;
+    let mut print_inverse = false;
+    let mut traced_seeds = Vec::new();
+    let mut reverse_queries = Vec::new();
+    let mut search_upward = false;
+    let mut lenient = false;
+    let mut almanac_format = None;
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--input" => input_path = next_arg(&mut args_iter, arg)?,
+            "--from" => source_type = Some(ResourceType::try_from(next_arg(&mut args_iter, arg)?)?),
+            "--to" => target_type = ResourceType::try_from(next_arg(&mut args_iter, arg)?)?,
+            "--print-map" => print_map = true,
+            "--print-inverse" => print_inverse = true,
+            "--trace-seed" => traced_seeds.push(next_arg(&mut args_iter, arg)?.parse()?),
+            "--reverse" => reverse_queries.push(parse_id_range(next_arg(&mut args_iter, arg)?)?),
+            "--search-upward" => search_upward = true,
+            "--lenient" => lenient = true,
+            "--write-almanac" => {
+                almanac_format = Some(AlmanacFormat::try_from(next_arg(&mut args_iter, arg)?)?)
+            }
+            _ => return Err(format!("Unknown day 5 option '{}'.", arg).into()),
+        }
+    }
+
+    let reader = BufReader::new(File::open(input_path)?);
+    let input_lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+    let issues = validate_almanac(&input_lines);
+    if !issues.is_empty() {
+        let report: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+        if !lenient {
+            return Err(report.join("\n").into());
+        }
+        for line in report {
+            eprintln!("Warning: {}", line);
+        }
+    }
+    let mut seeds = parse_seeds(&input_lines);
+    if let Some(format) = almanac_format {
+        let mappings = parse_resource_mappings(&input_lines);
+        print!("{}", format_almanac(&seeds, &mappings, format)?);
+        return Ok(());
+    }
+    let source_type = match (source_type, seeds.first()) {
+        (Some(res_type), _) => res_type,
+        (None, Some(seed)) => seed.res_type.clone(),
+        (None, None) => return Err("The almanac lists no ids on its first line.".into()),
+    };
+    for seed in &mut seeds {
+        seed.res_type = source_type.clone();
+    }
+    let graph = ResourceGraph::from(parse_resource_mappings(&input_lines));
+    let path = graph.find_path(&source_type, &target_type)?;
+
+    let seed_to_location = compose_mappings(&path);
     let location_min: u64 = seeds
         .iter()
-        .map(|s| get_mapping(&resource_maps, s, ResourceType::Location).id)
+        .map(|s| seed_to_location.apply(s.id))
         .min()
         .unwrap_or_default();
-    println!("Min location id values: {}", location_min);
-
-    //let location_min_range = seeds
-    //    .iter()
-    //    .step_by(2)
-    //    .zip(seeds.iter().skip(1).step_by(2))
-    //    .flat_map(|(r_start, num_elems)| r_start.id..(r_start.id + num_elems.id))
-    //    .map(|i| ResourceValue {
-    //        id: i,
-    //        res_type: ResourceType::Seed,
-    //    })
-    //    .map(|s| get_mapping(&resource_maps, &s, ResourceType::Location).id)
-    //    .min()
-    //    .unwrap_or_default();
-    //println!("Part 2 Min location id values: {}", location_min_range);
+    println!("Min {} id values: {}", target_type, location_min);
+
+    // The ids may be valid on their own but not as pairs of start and length,
+    // which only fails part 2.
+    match parse_seed_ranges(&seeds) {
+        Ok(seed_ranges) => {
+            let location_min_range = get_range_mapping(&path, &seed_ranges)
+                .iter()
+                .map(|range| range.start)
+                .min()
+                .unwrap_or_default();
+            println!(
+                "Part 2 Min {} id values: {}",
+                target_type, location_min_range
+            );
+            if search_upward {
+                println!(
+                    "Part 2 Min {} id values by upward search: {}",
+                    target_type,
+                    seed_to_location
+                        .search_upward(&seed_ranges)
+                        .ok_or("No seed range maps to any id.")?
+                );
+            }
+        }
+        Err(err) => eprintln!("Part 2 failed: {}", err),
+    }
+
+    if print_map {
+        println!("{} to {}:\n{}", source_type, target_type, seed_to_location);
+    }
+    if print_inverse {
+        println!(
+            "{} to {}:\n{}",
+            target_type,
+            source_type,
+            seed_to_location.invert()?
+        );
+    }
+    for seed in traced_seeds {
+        let value = ResourceValue {
+            id: seed,
+            res_type: source_type.clone(),
+        };
+        trace_value(&path, value)?;
+    }
+    for query in reverse_queries {
+        println!(
+            "{} {} <- {} {}",
+            target_type,
+            format_ranges(slice::from_ref(&query)),
+            source_type,
+            format_ranges(&seed_to_location.preimage(slice::from_ref(&query)))
+        );
+    }
+    Ok(())
+}
+
+pub fn print_answer() {
+    run(&[]).unwrap();
 }
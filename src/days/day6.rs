@@ -1,86 +1,507 @@
+use std::cmp::max;
+use std::cmp::min;
+use std::error::Error;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
-use std::time::Duration;
+use std::ops::RangeInclusive;
 
-#[derive(Debug)]
+use super::next_arg;
+
+/// A race of `time` ms with a record of `distance` mm. Holding the button is
+/// counted in whole ms, the record can only be beaten below `time² / 4` mm.
+#[derive(Debug, PartialEq)]
 struct BoatRace {
-    time: Duration,
-    distance: u64,
+    time: u64,
+    distance: u128,
+}
+
+/// A stretch of holding the button during which the speed grows by
+/// `acceleration` mm/ms for every ms held.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ChargePhase {
+    duration: u64,
+    acceleration: u64,
+}
+
+/// How holding the button turns into distance. The speed grows through the
+/// charge phases in order, then by `acceleration` mm/ms per ms held, up to
+/// `max_speed`. Every ms held costs `charge_cost` mm of the distance.
+#[derive(Debug, Clone, PartialEq)]
+struct Physics {
+    phases: Vec<ChargePhase>,
+    acceleration: u64,
+    max_speed: Option<u64>,
+    charge_cost: u64,
+}
+
+impl Default for Physics {
+    /// Speed equals hold time, as in the puzzle.
+    fn default() -> Self {
+        Physics {
+            phases: Vec::new(),
+            acceleration: 1,
+            max_speed: None,
+            charge_cost: 0,
+        }
+    }
+}
+
+impl TryFrom<&str> for ChargePhase {
+    type Error = Box<dyn Error>;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (duration, acceleration) = value
+            .split_once(':')
+            .ok_or_else(|| format!("Expected <ms>:<acceleration>, got '{}'.", value))?;
+        Ok(ChargePhase {
+            duration: duration.trim().parse()?,
+            acceleration: acceleration.trim().parse()?,
+        })
+    }
 }
 
-fn parse_races(input: &[String]) -> Vec<BoatRace> {
+fn parse_races(input: &[String]) -> Result<Vec<BoatRace>, Box<dyn Error>> {
     input
         .iter()
         .take(1)
         .flat_map(|l| {
             l.trim_start_matches(|c: char| !c.is_whitespace())
-                .trim()
                 .split_whitespace()
         })
         .zip(input.iter().skip(1).flat_map(|l| {
             l.trim_start_matches(|c: char| !c.is_whitespace())
-                .trim()
                 .split_whitespace()
         }))
-        .map(|(time_str, dist_str)| BoatRace {
-            time: Duration::from_millis(time_str.parse().unwrap()),
-            distance: dist_str.parse().unwrap(),
+        .map(|(time_str, dist_str)| {
+            Ok(BoatRace {
+                time: time_str
+                    .parse()
+                    .map_err(|err| format!("Bad race time '{}': {}.", time_str, err))?,
+                distance: dist_str
+                    .parse()
+                    .map_err(|err| format!("Bad record distance '{}': {}.", dist_str, err))?,
+            })
         })
         .collect()
 }
 
 /// s = v * t_up ms [mm]
 ///
-/// s = t_h mm/ms * t_up ms [mm]
+/// s = a mm/ms² * t_h ms * t_up ms - cost mm/ms * t_h ms [mm]
 ///
 /// t = t_h + t_up [ms]
 ///
 /// t_up = t - t_h [ms]
 ///
-/// s = t_h mm/ms * (t - t_h) ms [mm]
+/// s = a*t_h * (t - t_h) - cost*t_h [mm]
+///
+/// s = a*t*t_h - a*t_h² - cost*t_h  [mm]
 ///
-/// s = t*t_h - t_h²  [mm]
+/// 0 = -a*t_h² + (a*time - cost)*t_h - record_dist
 ///
-/// 0 = -t_h² + time*t_h - record_dist
-fn prod_num_victory(races: &[BoatRace]) -> usize {
+/// Returns the hold times between the integer roots, `None` if no hold time
+/// wins. Exact ties with the record do not win. Requires `a*time` to fit into
+/// `u64`.
+fn quadratic_hold_times(
+    acceleration: u64,
+    charge_cost: u64,
+    time: u64,
+    distance: u128,
+) -> Option<RangeInclusive<u64>> {
+    let a = u128::from(acceleration);
+    let wins = |hold: u64| {
+        a * u128::from(hold) * u128::from(time - hold)
+            > distance.saturating_add(u128::from(charge_cost) * u128::from(hold))
+    };
+    // 0 = -a*t_h² + b*t_h - distance
+    let b = (a * u128::from(time)).checked_sub(u128::from(charge_cost))?;
+    let vertex = (b / (2 * a)) as u64;
+    let peak = if vertex < time && !wins(vertex) {
+        vertex + 1
+    } else {
+        vertex
+    };
+    if !wins(peak) {
+        return None;
+    }
+    // Positive, as b² / 4a is at least the distance travelled at the peak.
+    let discriminant = b * b - 4 * a * distance;
+    // The integer square root rounds down, so these are within one of the
+    // roots, correct them to the first and last hold time beating the record.
+    let mut first = ((b - discriminant.isqrt()) / (2 * a)) as u64;
+    while !wins(first) {
+        first += 1;
+    }
+    while first > 0 && wins(first - 1) {
+        first -= 1;
+    }
+    let mut last = min(time, ((b + discriminant.isqrt()) / (2 * a)) as u64);
+    while !wins(last) {
+        last -= 1;
+    }
+    while last < time && wins(last + 1) {
+        last += 1;
+    }
+    Some(first..=last)
+}
+
+/// The first value in `lo..=hi` for which `pred` holds, where `pred` turns
+/// from false to true once.
+fn first_true(mut lo: u64, mut hi: u64, pred: impl Fn(u64) -> bool) -> Option<u64> {
+    if !pred(hi) {
+        return None;
+    }
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(mid) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    Some(lo)
+}
+
+/// The last value in `lo..=hi` for which `pred` holds, where `pred` turns
+/// from true to false once.
+fn last_true(mut lo: u64, mut hi: u64, pred: impl Fn(u64) -> bool) -> Option<u64> {
+    if !pred(lo) {
+        return None;
+    }
+    while lo < hi {
+        let mid = hi - (hi - lo) / 2;
+        if pred(mid) {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    Some(lo)
+}
+
+impl Physics {
+    /// Speed in mm/ms after holding the button for `hold` ms.
+    fn speed(&self, hold: u64) -> u128 {
+        let mut speed = 0u128;
+        let mut left = hold;
+        for phase in &self.phases {
+            let held = min(left, phase.duration);
+            speed += u128::from(held) * u128::from(phase.acceleration);
+            left -= held;
+        }
+        speed = speed.saturating_add(u128::from(left) * u128::from(self.acceleration));
+        match self.max_speed {
+            Some(max_speed) => min(speed, u128::from(max_speed)),
+            None => speed,
+        }
+    }
+
+    /// Distance travelled after the release, before the charging cost.
+    fn travelled(&self, time: u64, hold: u64) -> u128 {
+        self.speed(hold).saturating_mul(u128::from(time - hold))
+    }
+
+    fn wins(&self, time: u64, distance: u128, hold: u64) -> bool {
+        let cost = u128::from(self.charge_cost) * u128::from(hold);
+        self.travelled(time, hold) > distance.saturating_add(cost)
+    }
+
+    /// Hold times after which the speed grows differently: the ends of the
+    /// charge phases and the first hold time reaching `max_speed`.
+    fn breakpoints(&self, time: u64) -> Vec<u64> {
+        let mut points = vec![0, time];
+        let mut phase_end = 0u64;
+        for phase in &self.phases {
+            phase_end = phase_end.saturating_add(phase.duration);
+            points.push(min(phase_end, time));
+        }
+        if let Some(max_speed) = self.max_speed {
+            let capped = first_true(0, time, |hold| self.speed(hold) >= u128::from(max_speed));
+            points.extend(capped);
+        }
+        points.sort_unstable();
+        points.dedup();
+        points
+    }
+
+    /// Searches the winning hold times between every two breakpoints. The
+    /// speed grows linearly in between, so the net distance is concave there
+    /// and its winning hold times are one interval around its peak.
+    fn search_hold_times(&self, time: u64, distance: u128) -> Vec<RangeInclusive<u64>> {
+        let mut hold_times: Vec<RangeInclusive<u64>> = Vec::new();
+        for piece in self.breakpoints(time).windows(2) {
+            let (lo, hi) = (piece[0], piece[1]);
+            // The net distance stops growing where the gained distance no
+            // longer covers the charging cost.
+            let peak = first_true(lo, hi, |hold| {
+                hold == hi
+                    || self.travelled(time, hold + 1)
+                        <= self
+                            .travelled(time, hold)
+                            .saturating_add(u128::from(self.charge_cost))
+            })
+            .unwrap_or(hi);
+            let wins = |hold| self.wins(time, distance, hold);
+            if !wins(peak) {
+                continue;
+            }
+            let first = first_true(lo, peak, wins).unwrap_or(peak);
+            let last = last_true(peak, hi, wins).unwrap_or(peak);
+            match hold_times.last_mut() {
+                Some(prev) if *prev.end() >= first.saturating_sub(1) => {
+                    *prev = *prev.start()..=max(*prev.end(), last)
+                }
+                _ => hold_times.push(first..=last),
+            }
+        }
+        hold_times
+    }
+
+    /// The hold times beating the record, solving the quadratic directly
+    /// where the speed is proportional to the hold time.
+    fn winning_hold_times(&self, time: u64, distance: u128) -> Vec<RangeInclusive<u64>> {
+        let linear = self.phases.is_empty()
+            && self.max_speed.is_none()
+            && self.acceleration > 0
+            && self.acceleration.checked_mul(time).is_some();
+        if linear {
+            quadratic_hold_times(self.acceleration, self.charge_cost, time, distance)
+                .into_iter()
+                .collect()
+        } else {
+            self.search_hold_times(time, distance)
+        }
+    }
+}
+
+fn prod_num_victory(races: &[BoatRace], physics: &Physics) -> Result<u64, Box<dyn Error>> {
     races
         .iter()
         .map(|race| {
-            let inner_sqrt = (((race.time.as_millis() * race.time.as_millis()) as u64
-                - 4 * race.distance) as f64)
-                .sqrt();
-            let x_1 = (((race.time.as_millis() as f64) + inner_sqrt) / 2.0).floor() as u64;
-            let x_0 = (((race.time.as_millis() as f64) - inner_sqrt) / 2.0).ceil() as u64;
-            (x_0..=x_1).count()
+            physics
+                .winning_hold_times(race.time, race.distance)
+                .iter()
+                .map(|holds| holds.end() - holds.start() + 1)
+                .sum::<u64>()
         })
-        .product()
+        .try_fold(1u64, |product, count| product.checked_mul(count))
+        .ok_or_else(|| "Product of winning hold time counts overflows u64.".into())
 }
 
-fn parse_race(input: &[String]) -> BoatRace {
-    input
-        .iter()
-        .take(1)
-        .map(|l| l.chars().filter(|c| c.is_numeric()).collect::<String>())
-        .zip(
-            input
-                .iter()
-                .skip(1)
-                .map(|l| l.chars().filter(|c| c.is_numeric()).collect::<String>()),
-        )
-        .map(|(time_str, dist_str)| BoatRace {
-            time: Duration::from_millis(time_str.parse().unwrap()),
-            distance: dist_str.parse().unwrap(),
-        }).next().unwrap()
+/// Reads the digits of a line as one number, ignoring the spaces between
+/// them, e.g. `Time:      7  15   30` as 71530.
+fn parse_kerned(line: &str) -> Result<u128, Box<dyn Error>> {
+    let mut digits = line.chars().filter_map(|c| c.to_digit(10)).peekable();
+    if digits.peek().is_none() {
+        return Err(format!("No number in '{}'.", line).into());
+    }
+    digits
+        .try_fold(0u128, |value, digit| {
+            value.checked_mul(10)?.checked_add(u128::from(digit))
+        })
+        .ok_or_else(|| format!("Number in '{}' overflows u128.", line).into())
+}
+
+fn parse_race(input: &[String]) -> Result<BoatRace, Box<dyn Error>> {
+    let (time_line, dist_line) = match input {
+        [time_line, dist_line, ..] => (time_line, dist_line),
+        _ => return Err("Expected a time and a distance line.".into()),
+    };
+    let time = parse_kerned(time_line)?;
+    Ok(BoatRace {
+        time: u64::try_from(time).map_err(|_| format!("Race time {} overflows u64.", time))?,
+        distance: parse_kerned(dist_line)?,
+    })
+}
+
+#[test]
+fn test_quadratic_hold_times() {
+    assert_eq!(quadratic_hold_times(1, 0, 7, 9), Some(2..=5));
+    assert_eq!(quadratic_hold_times(1, 0, 15, 40), Some(4..=11));
+    assert_eq!(quadratic_hold_times(1, 0, 71530, 940200), Some(14..=71516));
+    // Holding 10 or 20 ms ties the record.
+    assert_eq!(quadratic_hold_times(1, 0, 30, 200), Some(11..=19));
+    assert_eq!(quadratic_hold_times(1, 0, 4, 4), None);
+    assert_eq!(quadratic_hold_times(1, 0, 4, 3), Some(2..=2));
+    assert_eq!(quadratic_hold_times(1, 0, 0, 0), None);
+    assert_eq!(quadratic_hold_times(1, 0, 7, 100), None);
+    assert_eq!(
+        quadratic_hold_times(1, 0, u64::MAX, 0),
+        Some(1..=(u64::MAX - 1))
+    );
+    assert_eq!(
+        quadratic_hold_times(1, 0, u64::MAX, u128::from(u64::MAX)),
+        Some(2..=(u64::MAX - 2))
+    );
+    // The best hold time travels k² or k² + k mm, close to u128::MAX.
+    let k = u64::MAX / 2;
+    let k_squared = u128::from(k) * u128::from(k);
+    assert_eq!(
+        quadratic_hold_times(1, 0, 2 * k, k_squared - 1),
+        Some(k..=k)
+    );
+    assert_eq!(quadratic_hold_times(1, 0, 2 * k, k_squared), None);
+    let (time, best) = (2 * k + 1, k_squared + u128::from(k));
+    assert_eq!(quadratic_hold_times(1, 0, time, best), None);
+    assert_eq!(
+        quadratic_hold_times(1, 0, time, best - 1),
+        Some(k..=(k + 1))
+    );
+    assert_eq!(quadratic_hold_times(1, 0, time, u128::MAX), None);
+
+    for time in 0..60u64 {
+        for distance in 0..=(time * time / 4 + 1) {
+            let wins: Vec<u64> = (0..=time)
+                .filter(|hold| hold * (time - hold) > distance)
+                .collect();
+            let expected = wins.first().map(|first| *first..=*wins.last().unwrap());
+            assert_eq!(quadratic_hold_times(1, 0, time, distance.into()), expected);
+        }
+    }
+}
+
+#[test]
+fn test_physics() {
+    let brute_force = |physics: &Physics, time: u64, distance: u128| {
+        let mut hold_times: Vec<RangeInclusive<u64>> = Vec::new();
+        for hold in (0..=time).filter(|hold| physics.wins(time, distance, *hold)) {
+            match hold_times.last_mut() {
+                Some(prev) if *prev.end() + 1 == hold => *prev = *prev.start()..=hold,
+                _ => hold_times.push(hold..=hold),
+            }
+        }
+        hold_times
+    };
+    for acceleration in 0..4 {
+        for charge_cost in 0..5 {
+            let physics = Physics {
+                acceleration,
+                charge_cost,
+                ..Physics::default()
+            };
+            for time in 0..30 {
+                for distance in (0..200).step_by(7) {
+                    let expected = brute_force(&physics, time, distance);
+                    assert_eq!(physics.winning_hold_times(time, distance), expected);
+                    assert_eq!(physics.search_hold_times(time, distance), expected);
+                }
+            }
+        }
+    }
+
+    let physics = Physics {
+        phases: vec![
+            ChargePhase {
+                duration: 3,
+                acceleration: 1,
+            },
+            ChargePhase {
+                duration: 1,
+                acceleration: 20,
+            },
+        ],
+        acceleration: 2,
+        max_speed: Some(30),
+        charge_cost: 1,
+    };
+    assert_eq!(physics.speed(3), 3);
+    assert_eq!(physics.speed(4), 23);
+    assert_eq!(physics.speed(6), 27);
+    assert_eq!(physics.speed(9), 30);
+    for time in 0..40 {
+        for distance in (0..300).step_by(3) {
+            let expected = brute_force(&physics, time, distance);
+            assert_eq!(physics.winning_hold_times(time, distance), expected);
+        }
+    }
+    // Holding 3 ms travels 6 mm and ties the record plus charging cost,
+    // holding 4 ms reaches the fast phase.
+    assert_eq!(physics.winning_hold_times(5, 3), vec![2..=2, 4..=4]);
+}
+
+#[test]
+fn test_parse_races() {
+    let lines = vec![
+        "Time:      7  15   30".to_string(),
+        "Distance:  9  40  200".to_string(),
+    ];
+    let races = parse_races(&lines).unwrap();
+    assert_eq!(races.len(), 3);
+    assert_eq!(prod_num_victory(&races, &Physics::default()).unwrap(), 288);
+    let race = parse_race(&lines).unwrap();
+    assert_eq!(
+        race,
+        BoatRace {
+            time: 71530,
+            distance: 940200
+        }
+    );
+
+    // A record past u64::MAX can still be beaten within a u64 race time.
+    let lines = vec![
+        "Time:      1000000 000000".to_string(),
+        "Distance:  4611686018 427387903 9999".to_string(),
+    ];
+    let race = parse_race(&lines).unwrap();
+    assert_eq!(race.distance, 46116860184273879039999);
+    assert!(prod_num_victory(&[race], &Physics::default()).unwrap() > 0);
+    assert!(parse_races(&lines).is_ok());
+    let lines = vec![
+        "Time:      18446744073709551616".to_string(),
+        "Distance:  340282366920938463463374607431768211456".to_string(),
+    ];
+    assert!(parse_races(&lines).is_err());
+    assert_eq!(
+        parse_race(&lines).unwrap_err().to_string(),
+        "Race time 18446744073709551616 overflows u64."
+    );
+    let lines = vec!["Time: 1".to_string(), lines[1].clone()];
+    assert_eq!(
+        parse_race(&lines).unwrap_err().to_string(),
+        "Number in 'Distance:  340282366920938463463374607431768211456' overflows u128."
+    );
+    assert!(parse_race(&lines[..1]).is_err());
+}
+
+/// Runs day 6 with command line options:
+///
+/// `--input <path>` reads the races from `path`,
+/// `--acceleration <mm/ms>` sets the speed gained per ms held after the
+/// charge phases, 1 by default,
+/// `--phase <ms>:<mm/ms>` adds a charge phase of the given length and
+/// acceleration, phases take effect in the order given,
+/// `--max-speed <mm/ms>` caps the speed,
+/// `--charge-cost <mm>` subtracts the given distance per ms held.
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut input_path = "data/input_day6";
+    let mut physics = Physics::default();
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--input" => input_path = next_arg(&mut args_iter, arg)?,
+            "--acceleration" => physics.acceleration = next_arg(&mut args_iter, arg)?.parse()?,
+            "--phase" => physics
+                .phases
+                .push(ChargePhase::try_from(next_arg(&mut args_iter, arg)?)?),
+            "--max-speed" => physics.max_speed = Some(next_arg(&mut args_iter, arg)?.parse()?),
+            "--charge-cost" => physics.charge_cost = next_arg(&mut args_iter, arg)?.parse()?,
+            _ => return Err(format!("Unknown day 6 option '{}'.", arg).into()),
+        }
+    }
+
+    let reader = BufReader::new(File::open(input_path)?);
+    let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+    let boat_races = parse_races(&lines)?;
+
+    println!("Num product {}", prod_num_victory(&boat_races, &physics)?);
+    let boat_race = parse_race(&lines)?;
+    println!(
+        "Part 2 product {}",
+        prod_num_victory(&[boat_race], &physics)?
+    );
+    Ok(())
 }
 
 pub fn print_answer() {
-    let reader = BufReader::new(File::open("data/input_day6").unwrap());
-    let lines: Vec<String> = reader.lines().filter_map(Result::ok).collect();
-    let boat_races = parse_races(&lines.as_slice());
-
-    //println!("{:?}", boat_races);
-    println!("Num product {}", prod_num_victory(&boat_races));
-    let boat_race = parse_race(&lines.as_slice());
-    println!("Part 2 product {}", prod_num_victory(&[boat_race]));
+    run(&[]).unwrap();
 }
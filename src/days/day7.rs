@@ -1,11 +1,17 @@
 use std::cmp::Ordering;
-use std::collections::BTreeMap;
-use std::collections::BinaryHeap;
+use std::error::Error;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+#[cfg(test)]
+use std::time::Instant;
 
-/// Represents each Card value
+use super::next_arg;
+#[cfg(test)]
+use super::XorShift;
+
+/// Represents each Card value. `J` is read as Jack or as Joker depending on
+/// the rule set, a Joker ranks below all other cards.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 enum Card {
     Joker,
@@ -18,15 +24,34 @@ enum Card {
     Eight,
     Nine,
     Ten,
-    //Jack,
+    Jack,
     Queen,
     King,
     Ace,
 }
 
-impl TryFrom<char> for Card {
-    type Error = &'static str;
-    fn try_from(value: char) -> Result<Self, Self::Error> {
+/// The rules hands are played by.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum RuleSet {
+    Standard,
+    /// `J` cards are Jokers, which act as whatever card makes the strongest
+    /// hand type.
+    JokersWild,
+}
+
+impl TryFrom<&str> for RuleSet {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "standard" => Ok(RuleSet::Standard),
+            "jokers-wild" => Ok(RuleSet::JokersWild),
+            _ => Err(format!("Unknown rule set '{}'.", value)),
+        }
+    }
+}
+
+impl Card {
+    fn parse(value: char, rules: RuleSet) -> Result<Self, &'static str> {
         match value {
             '2' => Ok(Card::Two),
             '3' => Ok(Card::Three),
@@ -37,8 +62,8 @@ impl TryFrom<char> for Card {
             '8' => Ok(Card::Eight),
             '9' => Ok(Card::Nine),
             'T' => Ok(Card::Ten),
-            //'J' => Ok(Card::Jack),
-            'J' => Ok(Card::Joker),
+            'J' if rules == RuleSet::JokersWild => Ok(Card::Joker),
+            'J' => Ok(Card::Jack),
             'Q' => Ok(Card::Queen),
             'K' => Ok(Card::King),
             'A' => Ok(Card::Ace),
@@ -47,7 +72,7 @@ impl TryFrom<char> for Card {
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 enum HandType {
     HighCard,
     OnePair,
@@ -58,127 +83,236 @@ enum HandType {
     FiveOfAKind,
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Debug)]
 struct Hand {
     cards: [Card; 5],
+    hand_type: HandType,
+    /// The hand type followed by the cards, four bits each, so that hands
+    /// order like their keys.
+    key: u32,
 }
 
-impl AsRef<HandType> for Hand {
-    fn as_ref(&self) -> &HandType {
-        let mut cards_count = [0u8; (Card::Ace as usize + 1)];
-        let mut joker_count = 0;
-        for card in self.cards {
-            if card == Card::Joker {
-                joker_count += 1;
-            } else {
-                cards_count[card as usize] += 1;
-            }
+/// Jokers count as the card most often in the hand, which makes the
+/// strongest hand type.
+fn hand_type(cards: &[Card; 5]) -> HandType {
+    let mut cards_count = [0u8; (Card::Ace as usize + 1)];
+    let mut joker_count = 0;
+    for card in cards {
+        if *card == Card::Joker {
+            joker_count += 1;
+        } else {
+            cards_count[*card as usize] += 1;
+        }
+    }
+
+    let (mut most, mut second) = (0, 0);
+    for count in cards_count {
+        if count > most {
+            second = most;
+            most = count;
+        } else if count > second {
+            second = count;
         }
+    }
+    match (most + joker_count, second) {
+        (5, _) => HandType::FiveOfAKind,
+        (4, _) => HandType::FourOfAKind,
+        (3, 2) => HandType::FullHouse,
+        (3, _) => HandType::ThreeOfAKind,
+        (2, 2) => HandType::TwoPair,
+        (2, _) => HandType::OnePair,
+        _ => HandType::HighCard,
+    }
+}
 
-        let mut heap = BinaryHeap::from(cards_count);
-        match (heap.pop(), joker_count) {
-            (Some(5), 0) => &HandType::FiveOfAKind,
-            (Some(4), 1) => &HandType::FiveOfAKind,
-            (Some(4), 0) => &HandType::FourOfAKind,
-            (Some(3), 2) => &HandType::FiveOfAKind,
-            (Some(3), 1) => &HandType::FourOfAKind,
-            (Some(3), 0) => match heap.pop() {
-                Some(2) => &HandType::FullHouse,
-                _ => &HandType::ThreeOfAKind,
-            },
-            (Some(2), 3) => &HandType::FiveOfAKind,
-            (Some(2), 2) => &HandType::FourOfAKind,
-            (Some(2), 1) => match heap.pop() {
-                Some(2) => &HandType::FullHouse,
-                _ => &HandType::ThreeOfAKind,
-            },
-            (Some(2), 0) => match heap.pop() {
-                Some(2) => &HandType::TwoPair,
-                _ => &HandType::OnePair,
-            },
-            (Some(1), 4) => &HandType::FiveOfAKind,
-            (Some(1), 3) => &HandType::FourOfAKind,
-            (Some(1), 2) => &HandType::ThreeOfAKind,
-            (Some(1), 1) => &HandType::OnePair,
-            (_, 5) => &HandType::FiveOfAKind,
-            _ => &HandType::HighCard,
+impl Hand {
+    fn new(cards: [Card; 5]) -> Self {
+        let hand_type = hand_type(&cards);
+        let key = cards
+            .iter()
+            .fold(hand_type as u32, |key, card| (key << 4) | *card as u32);
+        Hand {
+            cards,
+            hand_type,
+            key,
         }
     }
 }
 
 impl PartialOrd for Hand {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let lhs_hand_type: &HandType = self.as_ref();
-        let rhs_hand_type: &HandType = other.as_ref();
-        match lhs_hand_type.cmp(&rhs_hand_type) {
-            Ordering::Equal => {
-                for i in 0..self.cards.len() {
-                    let order = self.cards[i].cmp(&other.cards[i]);
-                    if order != Ordering::Equal {
-                        return Some(order);
-                    }
-                }
-                Some(Ordering::Equal)
-            }
-            ordering => Some(ordering),
-        }
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Hand {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap()
+        self.key.cmp(&other.key)
     }
 }
 
-impl TryFrom<&str> for Hand {
-    type Error = &'static str;
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
+impl Hand {
+    fn parse(value: &str, rules: RuleSet) -> Result<Self, &'static str> {
         let res = value
             .chars()
-            .map(Card::try_from)
-            .filter_map(Result::ok)
+            .map(|chr| Card::parse(chr, rules))
             .enumerate()
-            .try_fold(
-                (
-                    0usize,
-                    Hand {
-                        cards: [Card::Ace; 5],
-                    },
-                ),
-                |(_, mut hand), (idx, card)| {
-                    if idx < hand.cards.len() {
-                        hand.cards[idx] = card;
-                        return Ok((idx, hand));
-                    } else {
-                        return Err("Too many Cards for Hand.");
-                    }
-                },
-            )?;
-        if res.0 != res.1.cards.len() - 1 {
+            .try_fold((0usize, [Card::Ace; 5]), |(_, mut cards), (idx, card)| {
+                if idx < cards.len() {
+                    cards[idx] = card?;
+                    Ok((idx, cards))
+                } else {
+                    Err("Too many Cards for Hand.")
+                }
+            })?;
+        if res.0 != res.1.len() - 1 {
             return Err("Not enough Cards for Hand.");
         }
-        Ok(res.1)
+        Ok(Hand::new(res.1))
     }
 }
 
-pub fn print_answer() {
-    let reader = BufReader::new(File::open("data/input_day7").unwrap());
-    let lines: Vec<String> = reader.lines().filter_map(Result::ok).collect();
+/// Sums up the bids multiplied by the rank of their hand under `rules`.
+fn total_winnings(hands_n_bids: &[(&str, u64)], rules: RuleSet) -> Result<u64, Box<dyn Error>> {
+    let mut ranked = hands_n_bids
+        .iter()
+        .map(|(hand, bid)| Ok((Hand::parse(hand, rules)?, *bid)))
+        .collect::<Result<Vec<(Hand, u64)>, Box<dyn Error>>>()?;
+    ranked.sort_unstable_by_key(|(hand, _)| hand.key);
+    Ok(ranked
+        .iter()
+        .zip(1..)
+        .map(|((_, bid), rank)| bid * rank)
+        .sum())
+}
 
-    let hands_n_bids = BTreeMap::from_iter(
-        lines
-            .iter()
-            .filter_map(|line| line.trim().split_once(char::is_whitespace))
-            .map(|(hand, bid)| (Hand::try_from(hand).unwrap(), bid.parse::<u64>().unwrap()))
-            //.inspect(|(hand, _)| println!("{:?} {:?}", hand.cards, hand.as_ref())),
+#[test]
+fn test_rule_sets() {
+    let hands_n_bids = [
+        ("32T3K", 765),
+        ("T55J5", 684),
+        ("KK677", 28),
+        ("KTJJT", 220),
+        ("QQQJA", 483),
+    ];
+    assert_eq!(
+        total_winnings(&hands_n_bids, RuleSet::Standard).unwrap(),
+        6440
+    );
+    assert_eq!(
+        total_winnings(&hands_n_bids, RuleSet::JokersWild).unwrap(),
+        5905
     );
 
-    let sum_rank_mul_bids: u64 = hands_n_bids
-        .values()
-        .zip(1..)
-        .map(|(bid, rank)| bid * rank)
-        .sum();
+    let hand_type = |hand, rules| Hand::parse(hand, rules).unwrap().hand_type;
+    assert_eq!(hand_type("JJJJJ", RuleSet::Standard), HandType::FiveOfAKind);
+    assert_eq!(hand_type("KTJJT", RuleSet::Standard), HandType::TwoPair);
+    assert_eq!(
+        hand_type("KTJJT", RuleSet::JokersWild),
+        HandType::FourOfAKind
+    );
+    assert!(Hand::parse("JQQQQ", RuleSet::Standard) > Hand::parse("TQQQQ", RuleSet::Standard));
+    // Both are four of a kind, the Joker loses the tie-break.
+    assert!(Hand::parse("JKKK2", RuleSet::JokersWild) < Hand::parse("QQQQ2", RuleSet::JokersWild));
+    assert!(Hand::parse("JX234", RuleSet::Standard).is_err());
+}
+
+#[test]
+fn test_sort_key() {
+    // Every hand type, with and without Jokers, and a tie.
+    let hands = [
+        "AAAAA", "JJJJJ", "AAAAK", "JAAAK", "AAKKK", "JJKKA", "23456", "2345J", "22334", "J2233",
+        "T55J5", "KTJJT", "QQQJA", "32T3K", "KK677", "KK677", "AKQJT", "JKKK2", "QQQQ2",
+    ];
+    for rules in [RuleSet::Standard, RuleSet::JokersWild] {
+        let parsed: Vec<Hand> = hands
+            .iter()
+            .map(|hand| Hand::parse(hand, rules).unwrap())
+            .collect();
+        for lhs in &parsed {
+            for rhs in &parsed {
+                let expected = hand_type(&lhs.cards)
+                    .cmp(&hand_type(&rhs.cards))
+                    .then_with(|| lhs.cards.cmp(&rhs.cards));
+                assert_eq!(lhs.key.cmp(&rhs.key), expected);
+                assert_eq!(lhs.cmp(rhs), expected);
+            }
+        }
+    }
+}
+
+/// Times ranking a million random hands.
+#[test]
+#[ignore]
+fn bench_hand_sorting() {
+    let mut random = XorShift::new(0x9e3779b97f4a7c15);
+    let mut next_card = || b"23456789TJQKA"[(random.next_u64() % 13) as usize] as char;
+    let hands: Vec<String> = (0..1_000_000)
+        .map(|_| (0..5).map(|_| next_card()).collect())
+        .collect();
+
+    let start = Instant::now();
+    let mut ranked: Vec<Hand> = hands
+        .iter()
+        .map(|hand| Hand::parse(hand, RuleSet::JokersWild).unwrap())
+        .collect();
+    println!("Parsed {} hands in {:?}", ranked.len(), start.elapsed());
 
-    println!("Sum of bid rank products: {}", sum_rank_mul_bids);
+    let mut recomputed: Vec<[Card; 5]> = ranked.iter().map(|hand| hand.cards).collect();
+    let start = Instant::now();
+    recomputed.sort_by(|lhs, rhs| {
+        hand_type(lhs)
+            .cmp(&hand_type(rhs))
+            .then_with(|| lhs.cmp(rhs))
+    });
+    println!("Typed on every comparison: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    ranked.sort_unstable_by_key(|hand| hand.key);
+    println!("Sorted by key: {:?}", start.elapsed());
+    assert!(ranked
+        .iter()
+        .map(|hand| hand.cards)
+        .eq(recomputed.iter().copied()));
+}
+
+/// Runs day 7 with command line options:
+///
+/// `--input <path>` reads the hands from `path`,
+/// `--rules <standard|jokers-wild>` only plays by the given rule set instead
+/// of both.
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut input_path = "data/input_day7";
+    let mut rule_sets = vec![RuleSet::Standard, RuleSet::JokersWild];
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--input" => input_path = next_arg(&mut args_iter, arg)?,
+            "--rules" => rule_sets = vec![RuleSet::try_from(next_arg(&mut args_iter, arg)?)?],
+            _ => return Err(format!("Unknown day 7 option '{}'.", arg).into()),
+        }
+    }
+
+    let reader = BufReader::new(File::open(input_path)?);
+    let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+    let hands_n_bids = lines
+        .iter()
+        .filter_map(|line| line.trim().split_once(char::is_whitespace))
+        .map(|(hand, bid)| Ok((hand, bid.trim().parse::<u64>()?)))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    for rules in rule_sets {
+        let sum_rank_mul_bids = total_winnings(&hands_n_bids, rules)?;
+        match rules {
+            RuleSet::Standard => println!("Sum of bid rank products: {}", sum_rank_mul_bids),
+            RuleSet::JokersWild => {
+                println!("Part 2 Sum of bid rank products: {}", sum_rank_mul_bids)
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn print_answer() {
+    run(&[]).unwrap();
 }
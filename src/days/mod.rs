@@ -11,3 +11,35 @@ pub mod day6;
 pub mod day7;
 pub mod day8;
 pub mod day9;
+
+use std::error::Error;
+
+/// Takes the value following a command line `option` from `args`.
+fn next_arg<'a, I>(args: &mut I, option: &str) -> Result<&'a str, Box<dyn Error>>
+where
+    I: Iterator<Item = &'a String>,
+{
+    args.next()
+        .map(String::as_str)
+        .ok_or_else(|| format!("Missing value for option '{}'.", option).into())
+}
+
+/// Xorshift generator for the benchmarks, which are ignored by default.
+/// Run them with `cargo test --release -- --ignored --nocapture bench_`.
+#[cfg(test)]
+struct XorShift(u64);
+
+#[cfg(test)]
+impl XorShift {
+    /// Starts the sequence at `seed`, which must not be zero.
+    fn new(seed: u64) -> Self {
+        XorShift(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
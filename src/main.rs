@@ -1,6 +1,10 @@
 pub mod days;
 
-fn main() {
+use std::env;
+use std::error::Error;
+use std::process;
+
+fn print_all_answers() {
     println!("Day1:");
     days::day1::print_answer();
     println!("Day2:");
@@ -28,3 +32,29 @@ fn main() {
     println!("Day13:");
     days::day13::print_answer();
 }
+
+/// Runs a single day with its own options, e.g. `aoc2023 day2 --bag red=12`.
+fn run_day(day: &str, args: &[String]) -> Result<(), Box<dyn Error>> {
+    match day {
+        "day2" => days::day2::run(args),
+        "day3" => days::day3::run(args),
+        "day4" => days::day4::run(args),
+        "day5" => days::day5::run(args),
+        "day6" => days::day6::run(args),
+        "day7" => days::day7::run(args),
+        _ => Err(format!("Unknown command '{}'.", day).into()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.split_first() {
+        None => print_all_answers(),
+        Some((day, day_args)) => {
+            if let Err(err) = run_day(day, day_args) {
+                eprintln!("Error: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+}